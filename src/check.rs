@@ -0,0 +1,652 @@
+//! Finding everything wrong with a layout instead of stopping at the first problem,
+//! and pointing at where in which file it is. Elements are deserialized one at a time so
+//! each bad one gets reported, and a layout that loads is checked for things that are
+//! probably mistakes, like elements on top of each other.
+
+use std::{
+    collections::HashMap,
+    fmt, fs,
+    ops::Range,
+    path::{Path, PathBuf},
+};
+
+use color_eyre::Report;
+use log::{error, warn};
+use tiny_skia::{FillRule, Mask, Transform};
+use toml_edit::{Document, Item, Value};
+
+use crate::{
+    config::{self, ELEMENTS, Id, LayoutTable, Shape, TemplateFor},
+    gamepad::{Backend, Inputs, combine, tight_bounds},
+    vars::{Location, Step},
+};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Warning,
+    /// the layout won't load
+    Error,
+}
+
+#[derive(Clone, Debug)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub file: PathBuf,
+    /// the line and column (both starting at 1) that it's about, if it's somewhere
+    /// specific
+    pub location: Option<(usize, usize)>,
+    pub message: String,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        })
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.file.display())?;
+        if let Some((line, column)) = self.location {
+            write!(f, ":{line}:{column}")?;
+        }
+        write!(f, ": {}: {}", self.severity, self.message)
+    }
+}
+
+/// Everything wrong with the layout at `path`, in the order it shows up in its files.
+/// With a `backend` the ids are checked against its device too.
+pub fn check(path: &Path, backend: Option<&mut dyn Backend>) -> Vec<Diagnostic> {
+    let (layout, table) = match read(path) {
+        Ok(read) => read,
+        Err(diagnostic) => return vec![diagnostic],
+    };
+    let mut diagnostics = layout.deserialize(&table);
+    if diagnostics.is_empty() {
+        // anything else that could go wrong is in the files the layout refers to
+        match config::Gamepad::from_file(path) {
+            Ok((config, _)) => diagnostics = layout.lint(&config, backend),
+            Err(e) => diagnostics.push(layout.root().diagnostic(
+                Severity::Error,
+                None,
+                format!("{e:#}"),
+            )),
+        }
+    }
+    diagnostics.sort_by(|a, b| (&a.file, a.location).cmp(&(&b.file, b.location)));
+    diagnostics
+}
+
+/// The first thing that stops the layout from deserializing, without loading any of the
+/// files it refers to
+pub fn locate(path: &Path) -> Option<Diagnostic> {
+    let (layout, table) = read(path).ok()?;
+    let diagnostics = layout.deserialize(&table).into_iter();
+    diagnostics.min_by(|a, b| (&a.file, a.location).cmp(&(&b.file, b.location)))
+}
+
+/// The layout with everything it includes, along with where each part came from
+fn read(path: &Path) -> Result<(Layout, toml::Table), Diagnostic> {
+    let root = Source::read(path)?;
+    // reading every file first means that problems in them get pointed at
+    let mut sources = Vec::new();
+    let canonical = fs::canonicalize(path).unwrap_or(path.into());
+    root.includes(&mut vec![canonical], &mut sources)?;
+    sources.push(root);
+    let layout = Layout::new(sources);
+    match LayoutTable::read(path) {
+        Ok(LayoutTable { table, .. }) => Ok((layout, table)),
+        Err(e) => Err(layout.read_error(&e)),
+    }
+}
+
+/// Logs everything [`check`] finds, for when a layout fails to load
+pub fn log(path: &Path) {
+    for diagnostic in check(path, None) {
+        match diagnostic.severity {
+            Severity::Error => error!("{diagnostic}"),
+            Severity::Warning => warn!("{diagnostic}"),
+        }
+    }
+}
+
+/// One of the layout's files, parsed in a way that keeps track of where everything is
+struct Source {
+    path: PathBuf,
+    doc: Document<String>,
+}
+
+impl Source {
+    fn read(path: &Path) -> Result<Self, Diagnostic> {
+        let text = fs::read_to_string(path).map_err(|e| Diagnostic {
+            severity: Severity::Error,
+            file: path.to_owned(),
+            location: None,
+            message: format!("Couldn't read it: {e}"),
+        })?;
+        Self::parse(path, text)
+    }
+
+    fn parse(path: &Path, text: String) -> Result<Self, Diagnostic> {
+        match Document::parse(text.clone()) {
+            Ok(doc) => Ok(Self { path: path.to_owned(), doc }),
+            Err(e) => Err(Diagnostic {
+                severity: Severity::Error,
+                file: path.to_owned(),
+                location: location(&text, e.span()),
+                message: e.message().trim().to_owned(),
+            }),
+        }
+    }
+
+    /// Reads the files that this one includes (and the ones they include) into `sources`,
+    /// in the same order as [`LayoutTable::read`]
+    fn includes(
+        &self,
+        stack: &mut Vec<PathBuf>,
+        sources: &mut Vec<Source>,
+    ) -> Result<(), Diagnostic> {
+        let Some(key) = self.doc.key("include") else { return Ok(()) };
+        let error = |message| self.diagnostic(Severity::Error, key.span(), message);
+        let files = match self.doc.get("include").and_then(Item::as_value) {
+            Some(Value::String(file)) => vec![file.value().as_str()],
+            Some(Value::Array(files)) => (files.iter())
+                .map(|f| f.as_str().ok_or_else(|| error(format!("{f} isn't a file name"))))
+                .collect::<Result<_, _>>()?,
+            _ => return Err(error("should be a file or a list of files".to_owned())),
+        };
+        let dir = self.path.parent().unwrap_or(Path::new("."));
+        for file in files {
+            let file = dir.join(file);
+            let file = fs::canonicalize(&file).unwrap_or(file);
+            if stack.contains(&file) {
+                return Err(error(format!("{} ends up including itself", file.display())));
+            }
+            // a file that isn't there is the include's fault
+            let text = fs::read_to_string(&file)
+                .map_err(|e| error(format!("Couldn't include {}: {e}", file.display())))?;
+            let source = Source::parse(&file, text)?;
+            stack.push(file);
+            source.includes(stack, sources)?;
+            stack.pop();
+            sources.push(source);
+        }
+        Ok(())
+    }
+
+    fn diagnostic(
+        &self,
+        severity: Severity,
+        span: Option<Range<usize>>,
+        message: String,
+    ) -> Diagnostic {
+        Diagnostic {
+            severity,
+            file: self.path.clone(),
+            location: location(self.doc.raw(), span),
+            message,
+        }
+    }
+
+    /// How many of `key` are in this file itself
+    fn count(&self, key: &str) -> usize {
+        match self.doc.get(key) {
+            Some(Item::ArrayOfTables(elements)) => elements.len(),
+            Some(Item::Value(Value::Array(elements))) => elements.len(),
+            _ => 0,
+        }
+    }
+
+    /// The `index`th of this file's `key`, or one of its fields if it's there
+    fn element(
+        &self,
+        key: &str,
+        index: usize,
+        field: Option<&str>,
+    ) -> Option<Range<usize>> {
+        match self.doc.get(key)? {
+            Item::ArrayOfTables(elements) => {
+                let table = elements.get(index)?;
+                field.and_then(|f| table.key(f)?.span()).or_else(|| table.span())
+            }
+            Item::Value(Value::Array(elements)) => {
+                let value = elements.get(index)?;
+                (field.and_then(|f| value.as_inline_table()?.key(f)?.span()))
+                    .or_else(|| value.span())
+            }
+            _ => None,
+        }
+    }
+}
+
+fn location(text: &str, span: Option<Range<usize>>) -> Option<(usize, usize)> {
+    let before = text.get(..span?.start)?;
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    Some((before.matches('\n').count() + 1, before[line_start..].chars().count() + 1))
+}
+
+/// A layout's files and where each of its elements came from
+struct Layout {
+    /// everything it includes in the order their elements come in, then the layout
+    sources: Vec<Source>,
+    /// which source each element is from, and its index in that source
+    origins: HashMap<&'static str, Vec<(usize, usize)>>,
+}
+
+impl Layout {
+    fn new(sources: Vec<Source>) -> Self {
+        let origins = ELEMENTS
+            .into_iter()
+            .map(|key| {
+                let origins = (sources.iter().enumerate())
+                    .flat_map(|(s, source)| (0..source.count(key)).map(move |i| (s, i)))
+                    .collect();
+                (key, origins)
+            })
+            .collect();
+        Self { sources, origins }
+    }
+
+    fn root(&self) -> &Source {
+        self.sources.last().expect("there's always the layout itself")
+    }
+
+    /// About an element, pointing at `field` if it has one that it set itself
+    fn element(
+        &self,
+        severity: Severity,
+        (key, index): (&str, usize),
+        field: Option<&str>,
+        message: impl fmt::Display,
+    ) -> Diagnostic {
+        let message = format!("{key}[{index}]: {message}");
+        match self.origins.get(key).and_then(|o| o.get(index)) {
+            Some(&(s, i)) => {
+                let source = &self.sources[s];
+                source.diagnostic(severity, source.element(key, i, field), message)
+            }
+            None => self.root().diagnostic(severity, None, message),
+        }
+    }
+
+    /// About a top level option, in the last file that set it
+    fn option(&self, key: &str, message: impl fmt::Display) -> Diagnostic {
+        let message = format!("{key}: {message}");
+        match self.sources.iter().rev().find_map(|s| Some((s, s.doc.key(key)?))) {
+            Some((source, key)) => source.diagnostic(Severity::Error, key.span(), message),
+            None => self.root().diagnostic(Severity::Error, None, message),
+        }
+    }
+
+    /// Points errors from [`LayoutTable::read`] at the element or value that caused them
+    fn read_error(&self, e: &Report) -> Diagnostic {
+        let message = e.root_cause().to_string();
+        if let Some(&TemplateFor { key, index }) = e.downcast_ref() {
+            return self.element(Severity::Error, (key, index), Some("template"), message);
+        }
+        match e.downcast_ref::<Location>().map(|l| l.0.as_slice()) {
+            Some([Step::Key(key), Step::Index(i), rest @ ..])
+                if ELEMENTS.contains(&key.as_str()) =>
+            {
+                let field = match rest.first() {
+                    Some(Step::Key(field)) => Some(field.as_str()),
+                    _ => None,
+                };
+                self.element(Severity::Error, (key, *i), field, message)
+            }
+            Some([Step::Key(key), ..]) => self.option(key, message),
+            _ => self.root().diagnostic(Severity::Error, None, format!("{e:#}")),
+        }
+    }
+
+    /// Tries each option and element on its own so that every bad one gets reported
+    fn deserialize(&self, table: &toml::Table) -> Vec<Diagnostic> {
+        let mut diagnostics = Vec::new();
+        for (key, value) in table {
+            match value {
+                toml::Value::Array(elements) if ELEMENTS.contains(&key.as_str()) => {
+                    for (i, element) in elements.iter().enumerate() {
+                        if let Some((message, field)) = element_error(key, element) {
+                            let at = (key.as_str(), i);
+                            diagnostics.push(self.element(
+                                Severity::Error,
+                                at,
+                                field,
+                                message,
+                            ));
+                        }
+                    }
+                }
+                _ => {
+                    if let Err(e) = parse(key, value.clone()) {
+                        diagnostics.push(self.option(key, e.message()));
+                    }
+                }
+            }
+        }
+        diagnostics
+    }
+
+    /// Problems in a layout that loads, but probably doesn't look how it's meant to
+    fn lint(
+        &self,
+        config: &config::Gamepad,
+        backend: Option<&mut dyn Backend>,
+    ) -> Vec<Diagnostic> {
+        let mut diagnostics = Vec::new();
+
+        let empty = |shape: &Shape| match *shape {
+            Shape::Circle { radius } => radius <= 0.0,
+            Shape::RoundedRect { size: (w, h), .. } => w <= 0.0 || h <= 0.0,
+            Shape::Path { .. } | Shape::Svg { .. } => false,
+        };
+        let mut zero = |element, field, what: &str| {
+            let message = format!("{what} is zero, so there's nothing to draw");
+            diagnostics.push(self.element(Severity::Error, element, Some(field), message));
+        };
+        for (i, b) in config.buttons.iter().enumerate() {
+            if empty(b.shape.as_ref().unwrap_or(&config.button_shape)) {
+                zero(("buttons", i), "shape", "the shape's size");
+            }
+        }
+        for (i, a) in config.axes.iter().enumerate() {
+            if empty(a.shape.as_ref().unwrap_or(&config.axis_shape)) {
+                zero(("axes", i), "shape", "the shape's size");
+            }
+        }
+        for (i, s) in config.sticks.iter().enumerate() {
+            if s.shape.as_ref().is_some_and(empty) {
+                zero(("sticks", i), "shape", "the shape's size");
+            } else if s.shape.is_none() && s.radius.unwrap_or(config.stick_radius) <= 0.0 {
+                zero(("sticks", i), "radius", "the radius");
+            }
+            if s.gate_radius.or(config.gate_radius).is_some_and(|r| r <= 0.0) {
+                zero(("sticks", i), "gate_radius", "the gate's radius");
+            }
+        }
+        for (i, d) in config.dpads.iter().enumerate() {
+            if d.size.unwrap_or(config.dpad_size) <= 0.0 {
+                zero(("dpads", i), "size", "the size");
+            }
+        }
+
+        let mut duplicates = |key, ids: Vec<(usize, &Id)>, field| {
+            let mut seen = HashMap::new();
+            for (i, id) in ids {
+                if let Some(first) = seen.insert(id, i) {
+                    let message = format!("{id} is also used by {key}[{first}]");
+                    diagnostics.push(self.element(
+                        Severity::Warning,
+                        (key, i),
+                        field,
+                        message,
+                    ));
+                }
+            }
+        };
+        let ids = config.buttons.iter().map(|b| &b.id).enumerate().collect();
+        duplicates("buttons", ids, Some("id"));
+        let ids = config.axes.iter().map(|a| &a.id).enumerate().collect();
+        duplicates("axes", ids, Some("id"));
+        let ids = (config.sticks.iter().enumerate())
+            .flat_map(|(i, s)| [(i, &s.axes.0), (i, &s.axes.1)])
+            .collect();
+        duplicates("sticks", ids, Some("axes"));
+
+        // drawing the rest of the layout would panic on the empty shapes
+        if diagnostics.iter().any(|d| d.severity == Severity::Error) {
+            return diagnostics;
+        }
+        let inputs: Inputs = config.into();
+        let footprints: Vec<_> = (inputs.buttons.iter().enumerate())
+            .map(|(i, b)| (("buttons", i), vec![&b.path]))
+            .chain(
+                (inputs.axes.iter().enumerate()).map(|(i, a)| (("axes", i), vec![&a.path])),
+            )
+            .chain(inputs.sticks.iter().enumerate().map(|(i, s)| {
+                let gate = s.gate.as_ref().map(|(gate, ..)| gate);
+                (("sticks", i), gate.into_iter().chain([&s.path]).collect())
+            }))
+            .chain(
+                inputs.dpads.iter().enumerate().map(|(i, d)| {
+                    (("dpads", i), d.arms.iter().chain([&d.center]).collect())
+                }),
+            )
+            .collect();
+        for (n, (element, paths)) in footprints.iter().enumerate() {
+            for (other, other_paths) in &footprints[..n] {
+                if overlap(paths, other_paths) {
+                    let message = format!("overlaps {}[{}]", other.0, other.1);
+                    diagnostics.push(self.element(
+                        Severity::Warning,
+                        *element,
+                        None,
+                        message,
+                    ));
+                }
+            }
+        }
+
+        if let Some(backend) = backend {
+            backend.reload(&inputs);
+            for missing in backend.missing_ids() {
+                let field = match missing.key {
+                    "dpads" if config.dpads[missing.index].buttons.is_some() => "buttons",
+                    "buttons" | "axes" => "id",
+                    _ => "axes",
+                };
+                let message = format!("the device doesn't have {}", missing.id);
+                diagnostics.push(self.element(
+                    Severity::Warning,
+                    (missing.key, missing.index),
+                    Some(field),
+                    message,
+                ));
+            }
+        }
+        diagnostics
+    }
+}
+
+/// Deserializes a layout that only has `key`
+fn parse(key: &str, value: toml::Value) -> Result<config::Gamepad, toml::de::Error> {
+    toml::Table::from_iter([(key.to_owned(), value)]).try_into()
+}
+
+/// Why an element doesn't load, and the field to blame if it's down to one
+fn element_error<'a>(
+    key: &str,
+    element: &'a toml::Value,
+) -> Option<(String, Option<&'a str>)> {
+    match parse(key, vec![element.clone()].into()) {
+        Ok(config) => {
+            let history = config.history.first()?;
+            Some((history.validate().err()?.to_string(), Some("row_height")))
+        }
+        Err(e) if e.message() == config::Dpad::NO_SOURCE => {
+            Some((e.message().to_owned(), None))
+        }
+        Err(e) => Some((e.message().to_owned(), culprit(key, element))),
+    }
+}
+
+/// The field that stops an element from deserializing, found by leaving each one out
+fn culprit<'a>(key: &str, element: &'a toml::Value) -> Option<&'a str> {
+    let table = element.as_table()?;
+    let field = table.keys().find(|field| {
+        let mut without = table.clone();
+        without.remove(*field);
+        match parse(key, vec![toml::Value::Table(without)].into()) {
+            Ok(_) => true,
+            Err(e) => e.message() == format!("missing field `{field}`"),
+        }
+    })?;
+    Some(field)
+}
+
+/// Whether any pixel is covered by both sets of paths
+fn overlap(a: &[&tiny_skia::Path], b: &[&tiny_skia::Path]) -> bool {
+    let bounds =
+        |paths: &[&tiny_skia::Path]| paths.iter().map(|p| tight_bounds(p)).reduce(combine);
+    let (Some(a_bounds), Some(b_bounds)) = (bounds(a), bounds(b)) else { return false };
+    let Some(both) = a_bounds.intersect(&b_bounds) else { return false };
+    let (width, height) = (both.width().ceil() as u32, both.height().ceil() as u32);
+    let t = Transform::from_translate(-both.left(), -both.top());
+    let mask = |paths: &[&tiny_skia::Path]| {
+        let mut mask = Mask::new(width.max(1), height.max(1)).unwrap();
+        for path in paths {
+            mask.fill_path(path, FillRule::Winding, false, t);
+        }
+        mask
+    };
+    let (a, b) = (mask(a), mask(b));
+    a.data().iter().zip(b.data()).any(|(&a, &b)| a > 0 && b > 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+
+    /// Checks the first of `files` after writing them all to a new directory, and
+    /// returns the diagnostics with paths relative to it
+    fn check_files(name: &str, files: &[(&str, &str)]) -> Vec<String> {
+        let dir = env::temp_dir().join(format!("check-{}-{name}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for (file, text) in files {
+            fs::write(dir.join(file), text).unwrap();
+        }
+        let diagnostics = check(&dir.join(files[0].0), None);
+        let dir = fs::canonicalize(&dir).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        let dir = format!("{}/", dir.display());
+        diagnostics.iter().map(|d| d.to_string().replace(&dir, "")).collect()
+    }
+
+    #[test]
+    fn every_bad_element() {
+        let layout = r#"
+[[buttons]]
+id = 0
+pos = [0, 0]
+colour = "red"
+
+[[buttons]]
+id = 1
+pos = [50, 0]
+
+[[buttons]]
+id = 2
+pos = "over there"
+
+[[dpads]]
+pos = [100, 0]
+"#;
+        assert_eq!(
+            check_files("elements", &[("layout.toml", layout)]),
+            [
+                "layout.toml:5:1: error: buttons[0]: unknown field `colour`, expected \
+                 one of `id`, `pos`, `shape`, `fill`, `fill_active`, `outline_weight`, \
+                 `outline`, `outline_active`, `image`, `image_active`, `label`",
+                "layout.toml:13:1: error: buttons[2]: invalid type: string \
+                 \"over there\", expected a tuple of size 2",
+                "layout.toml:15:1: error: dpads[0]: needs either `axes` or `buttons`",
+            ]
+        );
+    }
+
+    #[test]
+    fn problems_in_included_files() {
+        let layout = "include = \"base.toml\"\nbuttons = [{ id = 1, pos = [50, 0] }]\n";
+        let base = "buttons = [{ id = 0, pos = [0, 0] }]\nactive = \"#fff\n";
+        assert_eq!(
+            check_files("syntax", &[("layout.toml", layout), ("base.toml", base)]),
+            ["base.toml:2:15: error: invalid basic string, expected `\"`"]
+        );
+
+        let layout = "# the base\ninclude = [\"base.toml\"]\n";
+        assert_eq!(
+            check_files("missing", &[("layout.toml", layout)]),
+            ["layout.toml:2:1: error: Couldn't include base.toml: No such file or \
+              directory (os error 2)"]
+        );
+
+        let files =
+            [("a.toml", "include = \"b.toml\""), ("b.toml", "include = \"a.toml\"")];
+        assert_eq!(
+            check_files("cycle", &files),
+            ["b.toml:1:1: error: a.toml ends up including itself"]
+        );
+    }
+
+    #[test]
+    fn templates_and_vars() {
+        let layout = r#"
+include = "base.toml"
+active = "$nope"
+
+[vars]
+x = 10
+
+[templates.round]
+shape = { radius = 10 }
+
+[[buttons]]
+id = 1
+pos = [40, 0]
+template = "round"
+
+[[buttons]]
+id = 2
+pos = [80, 0]
+template = "square"
+"#;
+        let base = "buttons = [{ id = 0, pos = [\"$x\", 0] }]";
+        assert_eq!(
+            check_files("templates", &[("layout.toml", layout), ("base.toml", base)]),
+            ["layout.toml:19:1: error: buttons[2]: there's no template called square"]
+        );
+
+        let layout = layout.replace("square", "round");
+        assert_eq!(
+            check_files("vars", &[("layout.toml", &layout), ("base.toml", base)]),
+            ["layout.toml:3:1: error: active: there's no $nope in [palette] or [vars]"]
+        );
+
+        let layout = layout.replace("$nope", "#fff");
+        let base = "buttons = [{ id = 0, pos = [\"$x\", \"$y\"] }]";
+        assert_eq!(
+            check_files("element vars", &[("layout.toml", &layout), ("base.toml", base)]),
+            ["base.toml:1:22: error: buttons[0]: there's no $y in [palette] or [vars]"]
+        );
+    }
+
+    #[test]
+    fn lints() {
+        let layout = r#"
+buttons = [
+    { id = "a", pos = [0, 0] },
+    { id = "a", pos = [100, 0] },
+    { id = "b", pos = [100, 10] },
+]
+"#;
+        assert_eq!(
+            check_files("lints", &[("layout.toml", layout)]),
+            [
+                "layout.toml:4:7: warning: buttons[1]: \"a\" is also used by buttons[0]",
+                "layout.toml:5:5: warning: buttons[2]: overlaps buttons[1]",
+            ]
+        );
+
+        let layout = "buttons = [{ id = 0, pos = [0, 0], shape = { radius = 0 } }]";
+        assert_eq!(
+            check_files("zero", &[("layout.toml", layout)]),
+            ["layout.toml:1:36: error: buttons[0]: the shape's size is zero, so there's \
+              nothing to draw"]
+        );
+    }
+}
@@ -1,11 +1,23 @@
 use std::{
+    collections::HashMap,
+    convert::Infallible,
     f32::consts::SQRT_2,
-    fmt, path,
+    fmt, fs, mem, path,
     path::PathBuf,
-    sync::mpsc::{self, Receiver},
+    str::FromStr,
+    sync::{
+        Arc,
+        mpsc::{self, Receiver},
+    },
     time::Duration,
 };
 
+use ab_glyph::{Font as _, FontArc, OutlineCurve, PxScale, ScaleFont};
+use color_eyre::{
+    Report,
+    eyre::{Context, bail, eyre},
+};
+use log::{debug, warn};
 use notify_debouncer_mini::{
     DebounceEventResult, DebouncedEvent, Debouncer, new_debouncer,
     notify::{self, RecommendedWatcher, RecursiveMode},
@@ -14,14 +26,24 @@ use serde::{
     Deserialize, Deserializer,
     de::{self, Unexpected, Visitor},
 };
-use tiny_skia::{self, Path, PathBuilder};
+use svgtypes::{SimplePathSegment, SimplifyingPathParser};
+use tiny_skia::{self, Path, PathBuilder, Pixmap, Rect, Transform};
 
-use crate::gamepad::{self, ColorPair};
+use crate::{
+    check,
+    controllerdb::Database,
+    gamepad::{self, ColorPair},
+    vars,
+};
 
 pub struct ConfigWatcher {
     pub debouncer: Debouncer<RecommendedWatcher>,
     pub rx: Receiver<DebouncedEvent>,
     pub path: Option<PathBuf>,
+    /// other files that the config uses, like svgs
+    pub dependencies: Vec<PathBuf>,
+    /// directories with dependencies that aren't already being watched
+    pub dependency_dirs: Vec<PathBuf>,
 }
 
 impl ConfigWatcher {
@@ -38,12 +60,41 @@ impl ConfigWatcher {
             .unwrap(),
             rx,
             path: None,
+            dependencies: Vec::new(),
+            dependency_dirs: Vec::new(),
+        }
+    }
+
+    /// Whether a change to `path` means the config needs to be reloaded
+    pub fn is_relevant(&self, path: &path::Path) -> bool {
+        self.path.as_deref() == Some(path) || self.dependencies.iter().any(|d| d == path)
+    }
+
+    /// Also watches the directories of files outside of the config's directory
+    pub fn set_dependencies(&mut self, dependencies: Vec<PathBuf>) -> notify::Result<()> {
+        let config_dir = self.path.as_ref().and_then(|p| p.parent());
+        let mut dirs: Vec<PathBuf> = dependencies
+            .iter()
+            .filter_map(|d| d.parent())
+            .filter(|d| config_dir.is_none_or(|c| !d.starts_with(c)))
+            .map(PathBuf::from)
+            .collect();
+        dirs.sort();
+        dirs.dedup();
+        for dir in self.dependency_dirs.iter().filter(|d| !dirs.contains(d)) {
+            self.debouncer.watcher().unwatch(dir)?;
+        }
+        for dir in dirs.iter().filter(|d| !self.dependency_dirs.contains(d)) {
+            self.debouncer.watcher().watch(dir, RecursiveMode::NonRecursive)?;
         }
+        self.dependencies = dependencies;
+        self.dependency_dirs = dirs;
+        Ok(())
     }
 
     pub fn change_file<P: AsRef<path::Path>>(&mut self, path: P) -> notify::Result<()> {
         let path = path.as_ref();
-        println!("{:?}", path);
+        debug!("watching {path:?}");
         if let Some(current) = &self.path {
             if current.as_path() == path {
                 return Ok(());
@@ -136,6 +187,19 @@ impl Color {
             (n << 4) as u8,
         )
     }
+
+    /// Any of the formats above, starting with a `#`
+    pub fn parse(value: &str) -> Option<Self> {
+        let hex = value.strip_prefix('#')?;
+        let n = u32::from_str_radix(hex, 16).ok()?;
+        match hex.len() {
+            6 => Some(Color::from_rgb(n)),
+            3 => Some(Color::from_rgb_packed(n)),
+            8 => Some(Color::from_rgba(n)),
+            4 => Some(Color::from_rgba_packed(n)),
+            _ => None,
+        }
+    }
 }
 
 impl<'de> Deserialize<'de> for Color {
@@ -160,36 +224,45 @@ impl<'de> Deserialize<'de> for Color {
                         &"Hex code starting with a '#'",
                     ));
                 }
-                let err = Err(de::Error::invalid_value(
-                    Unexpected::Str(value),
-                    &"RGB or RGBA hex code",
-                ));
-                match &value[1..] {
-                    rgb if rgb.len() == 6 => {
-                        u32::from_str_radix(rgb, 16).map(Color::from_rgb).or(err)
-                    }
-                    rgb if rgb.len() == 3 => {
-                        u32::from_str_radix(rgb, 16).map(Color::from_rgb_packed).or(err)
-                    }
-                    rgba if rgba.len() == 8 => {
-                        u32::from_str_radix(rgba, 16).map(Color::from_rgba).or(err)
-                    }
-                    rgba if rgba.len() == 4 => {
-                        u32::from_str_radix(rgba, 16).map(Color::from_rgba_packed).or(err)
-                    }
-                    _ => err,
-                }
+                Color::parse(value).ok_or_else(|| {
+                    de::Error::invalid_value(
+                        Unexpected::Str(value),
+                        &"RGB or RGBA hex code",
+                    )
+                })
             }
         }
         deserializer.deserialize_str(ColorVisitor)
     }
 }
 
-#[derive(Clone, Copy, Debug, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Deserialize)]
 #[serde(untagged, deny_unknown_fields)]
 pub enum Shape {
-    RoundedRect { size: (f32, f32), radius: Option<f32> },
-    Circle { radius: f32 },
+    RoundedRect {
+        size: (f32, f32),
+        radius: Option<f32>,
+    },
+    Circle {
+        radius: f32,
+    },
+    /// SVG path data relative to the element's position, scaled and then rotated
+    /// (clockwise in degrees) around that position
+    Path {
+        d: String,
+        scale: Option<f32>,
+        rotation: Option<f32>,
+    },
+    /// The element with `id` in an SVG file (relative to the layout), including its
+    /// transform. It's offset by the element's position, so `pos = [0, 0]` puts it
+    /// right where it is in the drawing.
+    Svg {
+        svg: PathBuf,
+        id: String,
+        /// filled in by [`Gamepad::from_file`]
+        #[serde(skip)]
+        path: Option<Path>,
+    },
 }
 
 impl Default for Shape {
@@ -198,10 +271,303 @@ impl Default for Shape {
     }
 }
 
+impl Shape {
+    /// Circles are centered on `(x, y)`, rectangles start there, and paths are relative
+    /// to it
+    pub fn path(&self, x: f32, y: f32) -> Path {
+        match self {
+            Shape::Circle { radius } => PathBuilder::from_circle(x, y, *radius).unwrap(),
+            Shape::RoundedRect { size, radius } => {
+                rounded_rect(x, y, size.0, size.1, radius.unwrap_or_default())
+            }
+            Shape::Path { d, scale, rotation } => {
+                let t = Transform::from_scale(scale.unwrap_or(1.0), scale.unwrap_or(1.0))
+                    .post_rotate(rotation.unwrap_or_default())
+                    .post_translate(x, y);
+                match parse_path(d)
+                    .and_then(|p| p.transform(t).ok_or("bad transform".into()))
+                {
+                    Ok(path) => path,
+                    Err(e) => {
+                        warn!("invalid path \"{d}\": {e}");
+                        Shape::default().path(x, y)
+                    }
+                }
+            }
+            Shape::Svg { path: Some(path), .. } => {
+                path.clone().transform(Transform::from_translate(x, y)).unwrap()
+            }
+            Shape::Svg { svg, id, path: None } => {
+                warn!("#{id} from {svg:?} was never loaded");
+                Shape::default().path(x, y)
+            }
+        }
+    }
+}
+
+/// Combines every path in a node, with their transforms applied
+fn svg_node_path(node: &usvg::Node, path: &mut PathBuilder) {
+    match node {
+        usvg::Node::Path(p) => {
+            if let Some(p) = p.data().clone().transform(p.abs_transform()) {
+                path.push_path(&p);
+            }
+        }
+        usvg::Node::Group(g) => {
+            for child in g.children() {
+                svg_node_path(child, path);
+            }
+        }
+        usvg::Node::Image(_) | usvg::Node::Text(_) => {}
+    }
+}
+
+fn parse_path(d: &str) -> Result<Path, String> {
+    let mut path = PathBuilder::new();
+    for segment in SimplifyingPathParser::from(d) {
+        match segment.map_err(|e| e.to_string())? {
+            SimplePathSegment::MoveTo { x, y } => path.move_to(x as f32, y as f32),
+            SimplePathSegment::LineTo { x, y } => path.line_to(x as f32, y as f32),
+            SimplePathSegment::Quadratic { x1, y1, x, y } => {
+                path.quad_to(x1 as f32, y1 as f32, x as f32, y as f32)
+            }
+            SimplePathSegment::CurveTo { x1, y1, x2, y2, x, y } => path
+                .cubic_to(x1 as f32, y1 as f32, x2 as f32, y2 as f32, x as f32, y as f32),
+            SimplePathSegment::ClosePath => path.close(),
+        }
+    }
+    path.finish().ok_or_else(|| "it's empty".into())
+}
+
+/// A png that's relative to the layout file
+#[derive(Clone, Debug, Deserialize)]
+#[serde(from = "PathBuf")]
+pub struct Image {
+    pub file: PathBuf,
+    /// filled in by [`Gamepad::from_file`]
+    pub pixmap: Option<Arc<Pixmap>>,
+}
+
+impl From<PathBuf> for Image {
+    fn from(file: PathBuf) -> Self {
+        Self { file, pixmap: None }
+    }
+}
+
+/// An image drawn behind everything else, with its top left corner at `pos`
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Background {
+    pub image: Image,
+    #[serde(default)]
+    pub pos: (f32, f32),
+}
+
+/// A ttf/otf file that's relative to the layout file
+#[derive(Clone, Debug, Deserialize)]
+#[serde(from = "PathBuf")]
+pub struct Font {
+    pub file: PathBuf,
+    /// filled in by [`Gamepad::from_file`]
+    pub font: Option<FontArc>,
+}
+
+impl From<PathBuf> for Font {
+    fn from(file: PathBuf) -> Self {
+        Self { file, font: None }
+    }
+}
+
+/// An SDL `gamecontrollerdb.txt` that's relative to the layout file
+#[derive(Clone, Debug, Deserialize)]
+#[serde(from = "PathBuf")]
+pub struct Mappings {
+    pub file: PathBuf,
+    /// filled in by [`Gamepad::from_file`]
+    pub db: Option<Arc<Database>>,
+}
+
+impl From<PathBuf> for Mappings {
+    fn from(file: PathBuf) -> Self {
+        Self { file, db: None }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub enum Align {
+    Left,
+    #[default]
+    Center,
+    Right,
+}
+
+/// Text drawn over a button, axis, or stick
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Label {
+    pub text: String,
+    /// defaults to the layout's `font`
+    pub font: Option<Font>,
+    pub size: Option<f32>,
+    pub color: Option<Color>,
+    pub color_active: Option<Color>,
+    #[serde(default)]
+    pub align: Align,
+    /// defaults to the center of the element, the text is vertically centered on it
+    pub pos: Option<(f32, f32)>,
+}
+
+impl Label {
+    pub fn load(&self, config: &Gamepad, center: (f32, f32)) -> Option<gamepad::Label> {
+        let Some(font) = self.font.as_ref().or(config.font.as_ref()) else {
+            warn!("label \"{}\" doesn't have a font", self.text);
+            return None;
+        };
+        let Some(font) = &font.font else {
+            warn!("{:?} was never loaded", font.file);
+            return None;
+        };
+        let color = self.color.unwrap_or(Color::new(0xff, 0xff, 0xff, 0xff));
+        Some(gamepad::Label {
+            path: text_path(
+                font,
+                &self.text,
+                self.size.unwrap_or(16.0),
+                self.align,
+                self.pos.unwrap_or(center),
+            )?,
+            color: ColorPair::new(self.color_active.unwrap_or(color).into(), color.into()),
+        })
+    }
+}
+
+/// Lays out a single line of text, `align` is relative to `x` and the line's ascent and
+/// descent are centered on `y`
+pub fn text_path(
+    font: &FontArc,
+    text: &str,
+    size: f32,
+    align: Align,
+    (x, y): (f32, f32),
+) -> Option<Path> {
+    let scaled = font.as_scaled(PxScale::from(size));
+    let glyphs: Vec<_> = text.chars().map(|c| font.glyph_id(c)).collect();
+    let mut offsets = Vec::with_capacity(glyphs.len());
+    let mut caret = 0.0;
+    for (i, &id) in glyphs.iter().enumerate() {
+        if i > 0 {
+            caret += scaled.kern(glyphs[i - 1], id);
+        }
+        offsets.push(caret);
+        caret += scaled.h_advance(id);
+    }
+    let left = match align {
+        Align::Left => x,
+        Align::Center => x - caret / 2.0,
+        Align::Right => x - caret,
+    };
+    let baseline = y + (scaled.ascent() + scaled.descent()) / 2.0;
+    let (sx, sy) = (scaled.h_scale_factor(), scaled.v_scale_factor());
+
+    let mut builder = PathBuilder::new();
+    for (id, offset) in glyphs.into_iter().zip(offsets) {
+        let Some(outline) = font.outline(id) else { continue };
+        // outlines are unscaled with y pointing up
+        let p = |p: ab_glyph::Point| (left + offset + p.x * sx, baseline - p.y * sy);
+        let mut last = None;
+        for curve in outline.curves {
+            let (start, end) = match curve {
+                OutlineCurve::Line(a, b) => (a, b),
+                OutlineCurve::Quad(a, _, b) => (a, b),
+                OutlineCurve::Cubic(a, _, _, b) => (a, b),
+            };
+            if last != Some(start) {
+                if last.is_some() {
+                    builder.close();
+                }
+                let (x, y) = p(start);
+                builder.move_to(x, y);
+            }
+            let (x, y) = p(end);
+            match curve {
+                OutlineCurve::Line(..) => builder.line_to(x, y),
+                OutlineCurve::Quad(_, c, _) => {
+                    let (cx, cy) = p(c);
+                    builder.quad_to(cx, cy, x, y)
+                }
+                OutlineCurve::Cubic(_, c1, c2, _) => {
+                    let ((x1, y1), (x2, y2)) = (p(c1), p(c2));
+                    builder.cubic_to(x1, y1, x2, y2, x, y)
+                }
+            }
+            last = Some(end);
+        }
+        if last.is_some() {
+            builder.close();
+        }
+    }
+    builder.finish()
+}
+
+fn center(path: &Path) -> (f32, f32) {
+    let b = gamepad::tight_bounds(path);
+    (b.x() + b.width() / 2.0, b.y() + b.height() / 2.0)
+}
+
+/// Which input on the controller an element shows. Numbers are whatever the backend
+/// calls it (which differs between controllers), names like `"south"`, `"left_trigger"`
+/// or `"dpad_up"` get looked up in the backend's standard mapping so that a layout works
+/// on anything that has those inputs.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Deserialize)]
+#[serde(untagged)]
+pub enum Id {
+    Index(u8),
+    Name(String),
+}
+
+impl Id {
+    /// `names` is the backend's table of standard names, and `index` turns a number into
+    /// whatever the backend uses
+    pub fn resolve<T: Copy>(
+        &self,
+        names: &[(&str, T)],
+        index: impl FnOnce(u8) -> Option<T>,
+    ) -> Option<T> {
+        match self {
+            Id::Index(i) => index(*i),
+            Id::Name(name) => names.iter().find(|(n, _)| n == name).map(|&(_, t)| t),
+        }
+    }
+}
+
+impl Default for Id {
+    fn default() -> Self {
+        Id::Index(0)
+    }
+}
+
+impl FromStr for Id {
+    type Err = Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(s.parse().map_or_else(|_| Id::Name(s.to_owned()), Id::Index))
+    }
+}
+
+impl fmt::Display for Id {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Id::Index(i) => write!(f, "{i}"),
+            Id::Name(name) => write!(f, "\"{name}\""),
+        }
+    }
+}
+
 #[derive(Clone, Debug, Deserialize)]
 #[serde(deny_unknown_fields)]
 pub struct Button {
-    pub id: u8,
+    pub id: Id,
     pub pos: (f32, f32),
     pub shape: Option<Shape>,
     pub fill: Option<Color>,
@@ -209,6 +575,11 @@ pub struct Button {
     pub outline_weight: Option<f32>,
     pub outline: Option<Color>,
     pub outline_active: Option<Color>,
+    /// stretched over the shape's bounds in place of the inactive fill
+    pub image: Option<Image>,
+    /// replaces the active fill, without it the active fill is drawn over `image`
+    pub image_active: Option<Image>,
+    pub label: Option<Label>,
 }
 
 impl Button {
@@ -223,15 +594,11 @@ impl Button {
         let weight = self.outline_weight.or(config.outline_weight).unwrap_or(2.0);
 
         let (x, y) = self.pos;
-        use Shape::*;
+        let path = self.shape.as_ref().unwrap_or(&config.button_shape).path(x, y);
         gamepad::Button {
-            id: self.id,
-            path: match self.shape.unwrap_or(config.button_shape) {
-                Circle { radius } => PathBuilder::from_circle(x, y, radius).unwrap(),
-                RoundedRect { size, radius } => {
-                    rounded_rect(x, y, size.0, size.1, radius.unwrap_or_default())
-                }
-            },
+            id: self.id.clone(),
+            label: self.label.as_ref().and_then(|l| l.load(config, center(&path))),
+            path,
             fill: ColorPair {
                 inactive: self.fill.unwrap_or(config.inactive).into(),
                 active: self.fill_active.unwrap_or(config.active).into(),
@@ -241,6 +608,8 @@ impl Button {
                 || self.outline.is_some()
                 || self.outline_active.is_some())
             .then(|| (ColorPair::new(outline_active, outline_inactive), weight)),
+            image: self.image.as_ref().and_then(|i| i.pixmap.clone()),
+            image_active: self.image_active.as_ref().and_then(|i| i.pixmap.clone()),
         }
     }
 }
@@ -249,13 +618,15 @@ impl Button {
 #[serde(deny_unknown_fields)]
 pub struct Stick {
     pub pos: (f32, f32),
-    pub axes: (u8, u8),
+    pub axes: (Id, Id),
     #[serde(default)]
     pub invert_x: bool,
     #[serde(default)]
     pub invert_y: bool,
     pub deadzone: Option<f32>,
     pub radius: Option<f32>,
+    /// replaces the circle for the knob, centered on `pos`
+    pub shape: Option<Shape>,
     pub displacement: Option<f32>,
     pub fill: Option<Color>,
     pub fill_active: Option<Color>,
@@ -263,9 +634,28 @@ pub struct Stick {
     pub outline: Option<Color>,
     pub outline_active: Option<Color>,
     pub gate_radius: Option<f32>,
+    pub gate_shape: Option<GateShape>,
     pub gate_weight: Option<f32>,
     pub gate: Option<Color>,
     pub gate_active: Option<Color>,
+    pub trail: Option<Trail>,
+    #[serde(default)]
+    pub style: StickStyle,
+    /// a line from the center of the gate to the knob
+    pub shaft: Option<Color>,
+    pub shaft_weight: Option<f32>,
+    /// moves along with the knob
+    pub label: Option<Label>,
+}
+
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub enum StickStyle {
+    /// the knob just moves around
+    #[default]
+    Flat,
+    /// the knob gets squashed in the direction it's pushed, like it's tilting
+    Tilt,
 }
 
 impl Stick {
@@ -280,12 +670,13 @@ impl Stick {
         let outline_inactive = self.outline.or(config.outline).unwrap_or_default().into();
         let outline_weight = self.outline_weight.or(config.outline_weight).unwrap_or(2.0);
         let (x, y) = self.pos;
-        let gate_path = PathBuilder::from_circle(
+        let gate_shape: gamepad::GateShape =
+            self.gate_shape.unwrap_or(config.gate_shape).into();
+        let gate_path = gate_shape.path(
             x,
             y,
             self.gate_radius.or(config.gate_radius).unwrap_or(r * 1.5),
-        )
-        .unwrap();
+        );
         let gate_active =
             self.gate_active.or(self.gate).or(config.outline).unwrap_or_default().into();
         let gate_inactive = self.gate.or(config.outline).unwrap_or_default().into();
@@ -296,11 +687,19 @@ impl Stick {
             .unwrap_or(4.0);
 
         gamepad::Stick {
-            x: gamepad::RawAxis { id: self.axes.0, invert: self.invert_x },
-            y: gamepad::RawAxis { id: self.axes.1, invert: self.invert_y },
+            x: gamepad::RawAxis { id: self.axes.0.clone(), invert: self.invert_x },
+            y: gamepad::RawAxis { id: self.axes.1.clone(), invert: self.invert_y },
             deadzone: self.deadzone.unwrap_or(0.05),
-            path: PathBuilder::from_circle(x, y, r).unwrap(),
+            path: match &self.shape {
+                Some(shape) => {
+                    let path = shape.path(0.0, 0.0);
+                    let (cx, cy) = center(&path);
+                    path.transform(Transform::from_translate(x - cx, y - cy)).unwrap()
+                }
+                None => PathBuilder::from_circle(x, y, r).unwrap(),
+            },
             displacement: self.displacement.unwrap_or(r * 3.0 / 4.0),
+            gate_shape,
             fill: ColorPair {
                 inactive: self.fill.unwrap_or(config.inactive).into(),
                 active: self.fill_active.unwrap_or(config.active).into(),
@@ -311,10 +710,212 @@ impl Stick {
                 || self.outline_active.is_some())
             .then(|| (ColorPair::new(outline_active, outline_inactive), outline_weight)),
             gate: (self.gate_radius.is_some()
+                || self.gate_shape.is_some()
                 || self.gate_weight.is_some()
                 || self.gate.is_some()
                 || self.gate_active.is_some())
             .then(|| (gate_path, ColorPair::new(gate_active, gate_inactive), gate_weight)),
+            trail: self.trail.as_ref().map(|t| gamepad::Trail {
+                length: t.length.unwrap_or(12),
+                interval: Duration::from_secs_f32(1.0 / t.rate.unwrap_or(60.0).max(1.0)),
+                color: t.color.or(self.fill_active).unwrap_or(config.active).into(),
+                width: t.width.unwrap_or(r / 4.0),
+                dots: t.style == TrailStyle::Dots,
+            }),
+            tilt: self.style == StickStyle::Tilt,
+            shaft: (self.shaft.is_some() || self.shaft_weight.is_some()).then(|| {
+                (
+                    self.shaft.or(config.outline).unwrap_or_default().into(),
+                    self.shaft_weight.unwrap_or(r / 3.0),
+                )
+            }),
+            label: self.label.as_ref().and_then(|l| l.load(config, self.pos)),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub enum TrailStyle {
+    #[default]
+    Line,
+    Dots,
+}
+
+/// Where the stick has been recently, fading out with age
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Trail {
+    /// how many samples to draw
+    pub length: Option<usize>,
+    /// samples per second, regardless of how often the overlay renders
+    pub rate: Option<f32>,
+    pub color: Option<Color>,
+    pub width: Option<f32>,
+    #[serde(default)]
+    pub style: TrailStyle,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields, remote = "Self")]
+pub struct Dpad {
+    pub pos: (f32, f32),
+    /// hat axes, most controllers report their dpad this way
+    pub axes: Option<(Id, Id)>,
+    /// up, down, left, right
+    pub buttons: Option<(Id, Id, Id, Id)>,
+    #[serde(default)]
+    pub invert_x: bool,
+    #[serde(default)]
+    pub invert_y: bool,
+    pub size: Option<f32>,
+    pub arm_width: Option<f32>,
+    pub fill: Option<Color>,
+    pub fill_active: Option<Color>,
+    pub up: Option<Color>,
+    pub down: Option<Color>,
+    pub left: Option<Color>,
+    pub right: Option<Color>,
+    pub outline_weight: Option<f32>,
+    pub outline: Option<Color>,
+    pub outline_active: Option<Color>,
+}
+
+/// Without `axes` or `buttons` there's nothing to read the directions from, so those
+/// dpads are rejected while deserializing
+impl<'de> Deserialize<'de> for Dpad {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let dpad = Dpad::deserialize(deserializer)?;
+        if dpad.axes.is_none() && dpad.buttons.is_none() {
+            return Err(de::Error::custom(Dpad::NO_SOURCE));
+        }
+        Ok(dpad)
+    }
+}
+
+impl Dpad {
+    pub const NO_SOURCE: &str = "needs either `axes` or `buttons`";
+
+    pub fn load(&self, config: &Gamepad) -> gamepad::Dpad {
+        let source = match (self.buttons.clone(), self.axes.clone()) {
+            (Some((up, down, left, right)), _) => {
+                gamepad::DpadSource::Buttons([up, down, left, right])
+            }
+            (None, Some((x, y))) => gamepad::DpadSource::Hat(
+                gamepad::RawAxis { id: x, invert: self.invert_x },
+                gamepad::RawAxis { id: y, invert: self.invert_y },
+            ),
+            (None, None) => unreachable!("rejected when deserializing"),
+        };
+
+        let size = self.size.unwrap_or(config.dpad_size);
+        let arm = self.arm_width.unwrap_or(size / 3.0);
+        let (x, y) = self.pos;
+        let (half, w) = (size / 2.0, arm / 2.0);
+        let rect =
+            |l, t, w, h| PathBuilder::from_rect(Rect::from_xywh(l, t, w, h).unwrap());
+        let arms = [
+            rect(x - w, y - half, arm, half - w),
+            rect(x - w, y + w, arm, half - w),
+            rect(x - half, y - w, half - w, arm),
+            rect(x + w, y - w, half - w, arm),
+        ];
+
+        let mut cross = PathBuilder::new();
+        cross.move_to(x - w, y - half);
+        for (dx, dy) in [
+            (w, -half),
+            (w, -w),
+            (half, -w),
+            (half, w),
+            (w, w),
+            (w, half),
+            (-w, half),
+            (-w, w),
+            (-half, w),
+            (-half, -w),
+            (-w, -w),
+        ] {
+            cross.line_to(x + dx, y + dy);
+        }
+        cross.close();
+
+        let inactive = self.fill.unwrap_or(config.inactive);
+        let active = self.fill_active.unwrap_or(config.active);
+        let fill = [self.up, self.down, self.left, self.right]
+            .map(|c| ColorPair::new(c.unwrap_or(active).into(), inactive.into()));
+
+        let outline_active = self
+            .outline_active
+            .or(self.outline)
+            .or(config.outline)
+            .unwrap_or_default()
+            .into();
+        let outline_inactive = self.outline.or(config.outline).unwrap_or_default().into();
+        let weight = self.outline_weight.or(config.outline_weight).unwrap_or(2.0);
+
+        gamepad::Dpad {
+            source,
+            arms,
+            center: rect(x - w, y - w, arm, arm),
+            fill,
+            outline: (config.default_outline()
+                || self.outline_weight.is_some()
+                || self.outline.is_some()
+                || self.outline_active.is_some())
+            .then(|| {
+                (
+                    cross.finish().unwrap(),
+                    ColorPair::new(outline_active, outline_inactive),
+                    weight,
+                )
+            }),
+        }
+    }
+}
+
+/// Either one of the named shapes, or a regular polygon where `rotation` is the angle
+/// (in degrees, clockwise from the right) of its first corner
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(untagged, deny_unknown_fields)]
+pub enum GateShape {
+    Named(NamedGate),
+    Polygon { sides: u8, rotation: Option<f32> },
+}
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NamedGate {
+    #[default]
+    Circle,
+    /// corners on the cardinals and diagonals like a gamecube controller
+    Octagon,
+    /// flat sides facing the cardinals
+    Square,
+}
+
+impl Default for GateShape {
+    fn default() -> Self {
+        Self::Named(NamedGate::Circle)
+    }
+}
+
+impl From<GateShape> for gamepad::GateShape {
+    fn from(shape: GateShape) -> Self {
+        let polygon = |sides: u8, degrees: f32| gamepad::GateShape::Polygon {
+            sides: sides.max(3),
+            rotation: degrees.to_radians(),
+        };
+        match shape {
+            GateShape::Named(NamedGate::Circle) => gamepad::GateShape::Circle,
+            GateShape::Named(NamedGate::Octagon) => polygon(8, 0.0),
+            GateShape::Named(NamedGate::Square) => polygon(4, 45.0),
+            GateShape::Polygon { sides, rotation } => {
+                polygon(sides, rotation.unwrap_or_default())
+            }
         }
     }
 }
@@ -333,7 +934,7 @@ pub enum FillDir {
 #[serde(deny_unknown_fields)]
 pub struct Axis {
     pub pos: (f32, f32),
-    pub id: u8,
+    pub id: Id,
     #[serde(default)]
     pub invert: bool,
     pub shape: Option<Shape>,
@@ -342,20 +943,15 @@ pub struct Axis {
     pub fill_active: Option<Color>,
     pub outline_weight: Option<f32>,
     pub outline: Option<Color>,
+    pub label: Option<Label>,
 }
 
 impl Axis {
     pub fn load(&self, config: &Gamepad) -> gamepad::Axis {
-        let axis = gamepad::RawAxis { id: self.id, invert: self.invert };
+        let axis = gamepad::RawAxis { id: self.id.clone(), invert: self.invert };
 
         let (x, y) = self.pos;
-        use Shape::*;
-        let path = match self.shape.unwrap_or(config.axis_shape) {
-            Circle { radius } => PathBuilder::from_circle(x, y, radius).unwrap(),
-            RoundedRect { size, radius } => {
-                rounded_rect(x, y, size.0, size.1, radius.unwrap_or_default())
-            }
-        };
+        let path = self.shape.as_ref().unwrap_or(&config.axis_shape).path(x, y);
 
         let direction = self.fill_dir.unwrap_or(config.fill_dir);
 
@@ -375,7 +971,239 @@ impl Axis {
             )
         });
 
-        gamepad::Axis { axis, path, direction, fill, outline }
+        let label = self.label.as_ref().and_then(|l| l.load(config, center(&path)));
+        gamepad::Axis { axis, path, direction, fill, outline, label }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub enum Units {
+    /// -1..1 for sticks and 0..1 for axes
+    #[default]
+    Normalized,
+    /// the normalized value stretched linearly over the readout's `range`. Backends
+    /// don't pass along what the device actually sent, so this only matches a device's
+    /// own units when `range` is set to them, e.g. `[0, 255]` for a gamecube stick.
+    Scaled,
+    /// whole units like melee uses internally, -80..80 for sticks and 0..140 for triggers
+    Melee,
+}
+
+/// Text showing the exact value of a stick or axis
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Readout {
+    pub pos: (f32, f32),
+    /// index of the stick (or axis) to show
+    pub stick: Option<usize>,
+    pub axis: Option<usize>,
+    #[serde(default)]
+    pub units: Units,
+    /// what -1..1 (or 0..1 for axes) maps to in scaled units
+    pub range: Option<(f32, f32)>,
+    /// `{x}` and `{y}` for sticks or `{value}` for axes, with an optional sign and
+    /// precision like `{x:+.2}`
+    pub format: Option<String>,
+    /// how many times a second the text can change
+    pub rate: Option<f32>,
+    pub font: Option<Font>,
+    pub size: Option<f32>,
+    pub color: Option<Color>,
+    #[serde(default)]
+    pub align: Align,
+}
+
+impl Readout {
+    pub fn load(&self, config: &Gamepad) -> Option<gamepad::Readout> {
+        let source = match (self.stick, self.axis) {
+            (Some(i), None) if i < config.sticks.len() => gamepad::ReadoutSource::Stick(i),
+            (None, Some(i)) if i < config.axes.len() => gamepad::ReadoutSource::Axis(i),
+            _ => {
+                warn!("readout at {:?} needs exactly one stick or axis", self.pos);
+                return None;
+            }
+        };
+        let Some(font) = self.font.as_ref().or(config.font.as_ref()) else {
+            warn!("readout at {:?} doesn't have a font", self.pos);
+            return None;
+        };
+        let Some(font) = font.font.clone() else {
+            warn!("{:?} was never loaded", font.file);
+            return None;
+        };
+        let format = self.format.clone().unwrap_or_else(|| match source {
+            gamepad::ReadoutSource::Stick(_) => "{x} {y}".into(),
+            gamepad::ReadoutSource::Axis(_) => "{value}".into(),
+        });
+        let mut readout = gamepad::Readout {
+            pos: self.pos,
+            source,
+            units: self.units,
+            range: self.range.unwrap_or((0.0, 255.0)),
+            format,
+            interval: self
+                .rate
+                .map(|rate| Duration::from_secs_f32(1.0 / rate.max(0.1)))
+                .unwrap_or_default(),
+            font,
+            size: self.size.unwrap_or(16.0),
+            align: self.align,
+            color: self.color.unwrap_or(Color::new(0xff, 0xff, 0xff, 0xff)).into(),
+            bounds: Rect::from_xywh(self.pos.0, self.pos.1, 1.0, 1.0)?,
+        };
+        // digits are usually all the same width, so the extremes are as wide as it gets
+        if let Some(bounds) = [(-1.0, -1.0), (1.0, 1.0)]
+            .into_iter()
+            .filter_map(|value| readout.path(value).map(|p| gamepad::tight_bounds(&p)))
+            .reduce(gamepad::combine)
+        {
+            readout.bounds = bounds;
+        }
+        Some(readout)
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub enum HistoryMode {
+    /// newest at the top, with the direction, buttons, and how many frames they were held
+    #[default]
+    List,
+    /// a row per button, scrolling right to left
+    Timeline,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct History {
+    pub pos: (f32, f32),
+    #[serde(default)]
+    pub mode: HistoryMode,
+    /// button ids to show, defaults to every button in the layout
+    pub buttons: Option<Vec<Id>>,
+    /// index of the stick (or dpad) that the direction in list mode comes from
+    pub stick: Option<usize>,
+    pub dpad: Option<usize>,
+    /// how many rows in list mode
+    pub length: Option<usize>,
+    pub row_height: Option<f32>,
+    /// how many seconds the timeline covers
+    pub duration: Option<f32>,
+    pub width: Option<f32>,
+    pub fill: Option<Color>,
+    /// for frame counts and arrows
+    pub color: Option<Color>,
+}
+
+impl History {
+    pub fn validate(&self) -> Result<(), Report> {
+        if self.row_height.is_some_and(|h| h <= 0.0 || h.is_nan()) {
+            bail!("`row_height` has to be bigger than 0");
+        }
+        Ok(())
+    }
+
+    pub fn load(&self, config: &Gamepad) -> gamepad::History {
+        let buttons = match &self.buttons {
+            Some(ids) => ids
+                .iter()
+                .filter_map(|id| {
+                    let i = config.buttons.iter().position(|b| b.id == *id);
+                    if i.is_none() {
+                        warn!(
+                            "history at {:?} shows button {id}, which isn't in the layout",
+                            self.pos
+                        );
+                    }
+                    i
+                })
+                .collect(),
+            None => (0..config.buttons.len()).collect(),
+        };
+        let direction = match (self.stick, self.dpad) {
+            (Some(i), _) if i < config.sticks.len() => {
+                Some(gamepad::HistorySource::Stick(i))
+            }
+            (None, Some(i)) if i < config.dpads.len() => {
+                Some(gamepad::HistorySource::Dpad(i))
+            }
+            (None, None) => None,
+            _ => {
+                warn!(
+                    "history at {:?} points at a stick/dpad that doesn't exist",
+                    self.pos
+                );
+                None
+            }
+        };
+        let row_height = self.row_height.unwrap_or(20.0);
+        let mode = match self.mode {
+            HistoryMode::List => {
+                gamepad::HistoryMode::List { length: self.length.unwrap_or(10) }
+            }
+            HistoryMode::Timeline => gamepad::HistoryMode::Timeline {
+                width: self.width.unwrap_or(300.0),
+                duration: Duration::from_secs_f32(self.duration.unwrap_or(3.0).max(0.1)),
+            },
+        };
+
+        gamepad::History {
+            mode,
+            pos: self.pos,
+            buttons,
+            direction,
+            row_height,
+            fill: self.fill.map(Into::into),
+            color: self.color.unwrap_or(config.active).into(),
+        }
+    }
+}
+
+/// Changes to the layout while the controller is disconnected, the colors replace
+/// every fill/outline color
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Disconnected {
+    pub opacity: Option<f32>,
+    pub inactive: Option<Color>,
+    pub outline: Option<Color>,
+    pub badge: Option<Badge>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Badge {
+    pub pos: (f32, f32),
+    pub radius: Option<f32>,
+    pub color: Option<Color>,
+    pub cross: Option<Color>,
+}
+
+impl Disconnected {
+    pub fn load(&self) -> gamepad::Disconnected {
+        gamepad::Disconnected {
+            opacity: self.opacity.unwrap_or(1.0).clamp(0.0, 1.0),
+            fill: self.inactive.map(Into::into),
+            outline: self.outline.map(Into::into),
+            badge: self.badge.as_ref().map(|b| {
+                let (x, y) = b.pos;
+                let r = b.radius.unwrap_or(10.0);
+                // the X fits inside the circle with a bit of margin
+                let d = r / 2.0;
+                let mut cross = PathBuilder::new();
+                cross.move_to(x - d, y - d);
+                cross.line_to(x + d, y + d);
+                cross.move_to(x + d, y - d);
+                cross.line_to(x - d, y + d);
+                (
+                    PathBuilder::from_circle(x, y, r).unwrap(),
+                    b.color.unwrap_or(Color::new(0xe0, 0x30, 0x30, 0xff)).into(),
+                    cross.finish().unwrap(),
+                    b.cross.unwrap_or(Color::new(0xff, 0xff, 0xff, 0xff)).into(),
+                )
+            }),
+        }
     }
 }
 
@@ -391,6 +1219,10 @@ const fn default_stick() -> f32 {
     40.0
 }
 
+const fn default_dpad() -> f32 {
+    80.0
+}
+
 const fn default_axis() -> Shape {
     Shape::RoundedRect { size: (120.0, 20.0), radius: Some(5.0) }
 }
@@ -415,17 +1247,414 @@ pub struct Gamepad {
     #[serde(default)]
     pub gate_radius: Option<f32>,
     #[serde(default)]
+    pub gate_shape: GateShape,
+    #[serde(default)]
     pub fill_dir: FillDir,
+    #[serde(default = "default_dpad")]
+    pub dpad_size: f32,
     #[serde(default)]
     pub buttons: Vec<Button>,
     #[serde(default)]
     pub sticks: Vec<Stick>,
     #[serde(default)]
     pub axes: Vec<Axis>,
+    #[serde(default)]
+    pub dpads: Vec<Dpad>,
+    #[serde(default)]
+    pub history: Vec<History>,
+    #[serde(default)]
+    pub readouts: Vec<Readout>,
+    /// maps evdev key names to button ids for the keyboard backend
+    #[serde(default)]
+    pub keys: HashMap<String, Id>,
+    /// controller mappings for the usb backend, on top of the bundled ones
+    pub mappings: Option<Mappings>,
+    #[serde(default)]
+    pub disconnected: Option<Disconnected>,
+    #[serde(default)]
+    pub background: Option<Background>,
+    /// used by labels that don't have their own
+    #[serde(default)]
+    pub font: Option<Font>,
+    /// how many of each kind of element came from `include`d files, which come before
+    /// the layout's own
+    #[serde(skip)]
+    pub included: HashMap<&'static str, usize>,
+}
+
+/// Arrays of elements, which get concatenated by includes and can use templates
+pub const ELEMENTS: [&str; 6] =
+    ["buttons", "sticks", "axes", "dpads", "history", "readouts"];
+/// Values that are paths relative to the file they're in
+const PATHS: [&str; 5] = ["svg", "image", "image_active", "font", "mappings"];
+
+/// Reads a layout and returns everything it `include`s merged together, and then the
+/// layout's own values (which go on top). Every file that got included is added to
+/// `files`, and `stack` is the chain of includes that led here.
+fn read_layout(
+    path: &path::Path,
+    stack: &mut Vec<PathBuf>,
+    files: &mut Vec<PathBuf>,
+) -> Result<(toml::Table, toml::Table), Report> {
+    let contents = fs::read_to_string(path)
+        .with_context(|| format!("Couldn't read {}", path.display()))?;
+    let mut own: toml::Table = toml::from_str(&contents)
+        .with_context(|| format!("Couldn't parse {}", path.display()))?;
+    let dir = path.parent().unwrap_or(path::Path::new("."));
+
+    let includes = match own.remove("include") {
+        None => Vec::new(),
+        Some(toml::Value::String(file)) => vec![file],
+        Some(toml::Value::Array(files)) => files
+            .into_iter()
+            .map(|f| match f {
+                toml::Value::String(file) => Ok(file),
+                other => Err(eyre!("`include` has {other}, which isn't a file name")),
+            })
+            .collect::<Result<_, _>>()?,
+        Some(other) => bail!("`include` should be a file or a list of files, not {other}"),
+    };
+    let mut merged = toml::Table::new();
+    for file in includes {
+        let file = dir.join(file);
+        let file = fs::canonicalize(&file).unwrap_or(file);
+        if stack.contains(&file) {
+            bail!("{} ends up including itself", file.display());
+        }
+        stack.push(file.clone());
+        let (mut included, mut theirs) = read_layout(&file, stack, files)?;
+        stack.pop();
+        relative_to(&mut theirs, file.parent().unwrap_or(path::Path::new(".")));
+        merge_layout(&mut included, theirs);
+        merge_layout(&mut merged, included);
+        files.push(file);
+    }
+    Ok((merged, own))
+}
+
+/// Deep merges `over` into `base`: tables are merged key by key, and anything else
+/// (including arrays) gets replaced
+fn merge(base: &mut toml::Table, over: toml::Table) {
+    for (key, value) in over {
+        match (base.get_mut(&key), value) {
+            (Some(toml::Value::Table(base)), toml::Value::Table(over)) => merge(base, over),
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+/// Merges a whole layout on top of another, which is the same as [`merge`] except that
+/// the arrays of elements are concatenated
+fn merge_layout(base: &mut toml::Table, over: toml::Table) {
+    for (key, value) in over {
+        match (base.get_mut(&key), value) {
+            (Some(toml::Value::Array(base)), toml::Value::Array(over))
+                if ELEMENTS.contains(&key.as_str()) =>
+            {
+                base.extend(over)
+            }
+            (Some(toml::Value::Table(base)), toml::Value::Table(over)) => merge(base, over),
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+/// Makes the paths in an included file absolute, since everything else is relative to
+/// the layout that included it
+fn relative_to(table: &mut toml::Table, dir: &path::Path) {
+    for (key, value) in table.iter_mut() {
+        match value {
+            toml::Value::String(file) if PATHS.contains(&key.as_str()) => {
+                *file = dir.join(&file).to_string_lossy().into_owned();
+            }
+            toml::Value::Table(table) => relative_to(table, dir),
+            toml::Value::Array(values) => {
+                for table in values.iter_mut().filter_map(toml::Value::as_table_mut) {
+                    relative_to(table, dir);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// A template with the ones it's based on merged under it
+fn template(
+    templates: &toml::Table,
+    name: &toml::Value,
+    seen: &mut Vec<String>,
+) -> Result<toml::Table, Report> {
+    let toml::Value::String(name) = name else {
+        bail!("template names should be strings, not {name}");
+    };
+    if seen.contains(name) {
+        bail!("template {name} ends up using itself");
+    }
+    let Some(toml::Value::Table(template)) = templates.get(name) else {
+        bail!("there's no template called {name}");
+    };
+    let mut template = template.clone();
+    match template.remove("template") {
+        Some(base) => {
+            seen.push(name.clone());
+            let mut base = self::template(templates, &base, seen)?;
+            merge(&mut base, template);
+            Ok(base)
+        }
+        None => Ok(template),
+    }
+}
+
+/// Which element couldn't use its template, as the context of errors from
+/// [`LayoutTable::read`]
+#[derive(Debug)]
+pub struct TemplateFor {
+    pub key: &'static str,
+    pub index: usize,
+}
+
+impl fmt::Display for TemplateFor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Couldn't use a template for {} {}", self.key, self.index)
+    }
+}
+
+/// Fills in elements that say `template = "name"` from `[templates.name]`
+fn apply_templates(table: &mut toml::Table) -> Result<(), Report> {
+    let templates = match table.remove("templates") {
+        None => toml::Table::new(),
+        Some(toml::Value::Table(templates)) => templates,
+        Some(other) => bail!("`templates` should be a table, not {other}"),
+    };
+    for key in ELEMENTS {
+        let Some(toml::Value::Array(elements)) = table.get_mut(key) else { continue };
+        for (i, element) in elements.iter_mut().enumerate() {
+            let Some(element) = element.as_table_mut() else { continue };
+            let Some(name) = element.remove("template") else { continue };
+            let mut base = template(&templates, &name, &mut Vec::new())
+                .with_context(|| TemplateFor { key, index: i })?;
+            merge(&mut base, mem::take(element));
+            *element = base;
+        }
+    }
+    Ok(())
+}
+
+/// A layout with its includes, templates and vars applied, so it's ready to deserialize
+pub struct LayoutTable {
+    pub table: toml::Table,
+    /// see [`Gamepad::included`]
+    pub included: HashMap<&'static str, usize>,
+    /// every included file, in the order that their elements come in
+    pub includes: Vec<PathBuf>,
+}
+
+impl LayoutTable {
+    pub fn read(path: &path::Path) -> Result<Self, Report> {
+        let canonical = fs::canonicalize(path).unwrap_or(path.into());
+        let mut includes = Vec::new();
+        let (mut table, own) = read_layout(path, &mut vec![canonical], &mut includes)?;
+        let included = ELEMENTS
+            .into_iter()
+            .map(|key| {
+                (key, table.get(key).and_then(toml::Value::as_array).map_or(0, Vec::len))
+            })
+            .collect();
+        merge_layout(&mut table, own);
+        apply_templates(&mut table)?;
+        vars::substitute(&mut table)?;
+        Ok(Self { table, included, includes })
+    }
+}
+
+/// The merged table doesn't know which file anything came from, so errors from
+/// deserializing it get pointed at the right line by checking the layout bit by bit
+fn located(path: &path::Path, message: impl fmt::Display) -> Report {
+    match check::locate(path) {
+        Some(diagnostic) => eyre!("{diagnostic}"),
+        None => eyre!("Couldn't load {}: {message}", path.display()),
+    }
 }
 
 impl Gamepad {
     pub fn default_outline(&self) -> bool {
         self.outline_weight.is_some() || self.outline.is_some()
     }
+
+    /// Reads a layout along with any files that it refers to, and returns the paths of
+    /// those files so they can be watched too
+    pub fn from_file(path: &path::Path) -> Result<(Self, Vec<PathBuf>), Report> {
+        let LayoutTable { table, included, includes } = LayoutTable::read(path)?;
+        let mut config: Self =
+            table.try_into().map_err(|e: toml::de::Error| located(path, e.message()))?;
+        for (i, history) in config.history.iter().enumerate() {
+            history.validate().map_err(|e| located(path, format!("history[{i}]: {e}")))?;
+        }
+        config.included = included;
+        let dir = path.parent().unwrap_or(path::Path::new("."));
+
+        let mut svgs = HashMap::new();
+        for shape in config.shapes_mut() {
+            let Shape::Svg { svg, id, path } = shape else { continue };
+            let file = dir.join(&svg);
+            let file = fs::canonicalize(&file).unwrap_or(file);
+            if !svgs.contains_key(&file) {
+                let data = fs::read(&file)
+                    .with_context(|| format!("Couldn't read {}", file.display()))?;
+                let tree = usvg::Tree::from_data(&data, &usvg::Options::default())
+                    .with_context(|| format!("Couldn't parse {}", file.display()))?;
+                svgs.insert(file.clone(), tree);
+            }
+            let node = svgs[&file].node_by_id(id).ok_or_else(|| {
+                eyre!("{} doesn't have an element with id {id}", file.display())
+            })?;
+            let mut builder = PathBuilder::new();
+            svg_node_path(node, &mut builder);
+            *path = Some(
+                builder
+                    .finish()
+                    .ok_or_else(|| eyre!("#{id} in {} has no paths", file.display()))?,
+            );
+        }
+
+        let mut pixmaps = HashMap::new();
+        for image in config.images_mut() {
+            let file = dir.join(&image.file);
+            let file = fs::canonicalize(&file).unwrap_or(file);
+            if !pixmaps.contains_key(&file) {
+                let pixmap = Pixmap::load_png(&file)
+                    .with_context(|| format!("Couldn't load {}", file.display()))?;
+                pixmaps.insert(file.clone(), Arc::new(pixmap));
+            }
+            image.pixmap = Some(pixmaps[&file].clone());
+        }
+
+        let mut fonts = HashMap::new();
+        for font in config.fonts_mut() {
+            let file = dir.join(&font.file);
+            let file = fs::canonicalize(&file).unwrap_or(file);
+            if !fonts.contains_key(&file) {
+                let data = fs::read(&file)
+                    .with_context(|| format!("Couldn't read {}", file.display()))?;
+                let loaded = FontArc::try_from_vec(data)
+                    .with_context(|| format!("Couldn't parse {}", file.display()))?;
+                fonts.insert(file.clone(), loaded);
+            }
+            font.font = Some(fonts[&file].clone());
+        }
+
+        let mut mappings = None;
+        if let Some(m) = &mut config.mappings {
+            let file = dir.join(&m.file);
+            let file = fs::canonicalize(&file).unwrap_or(file);
+            let text = fs::read_to_string(&file)
+                .with_context(|| format!("Couldn't read {}", file.display()))?;
+            m.db = Some(Arc::new(Database::new(Some(&text))));
+            mappings = Some(file);
+        }
+
+        let dependencies = svgs
+            .into_keys()
+            .chain(pixmaps.into_keys())
+            .chain(fonts.into_keys())
+            .chain(mappings)
+            .chain(includes);
+        Ok((config, dependencies.collect()))
+    }
+
+    fn shapes_mut(&mut self) -> impl Iterator<Item = &mut Shape> {
+        [&mut self.button_shape, &mut self.axis_shape]
+            .into_iter()
+            .chain(self.buttons.iter_mut().filter_map(|b| b.shape.as_mut()))
+            .chain(self.axes.iter_mut().filter_map(|a| a.shape.as_mut()))
+            .chain(self.sticks.iter_mut().filter_map(|s| s.shape.as_mut()))
+    }
+
+    fn images_mut(&mut self) -> impl Iterator<Item = &mut Image> {
+        let buttons =
+            self.buttons.iter_mut().flat_map(|b| [&mut b.image, &mut b.image_active]);
+        self.background
+            .iter_mut()
+            .map(|b| &mut b.image)
+            .chain(buttons.filter_map(Option::as_mut))
+    }
+
+    fn fonts_mut(&mut self) -> impl Iterator<Item = &mut Font> {
+        let labels = (self.buttons.iter_mut().map(|b| &mut b.label))
+            .chain(self.axes.iter_mut().map(|a| &mut a.label))
+            .chain(self.sticks.iter_mut().map(|s| &mut s.label));
+        self.font
+            .iter_mut()
+            .chain(labels.filter_map(|l| l.as_mut().and_then(|l| l.font.as_mut())))
+            .chain(self.readouts.iter_mut().filter_map(|r| r.font.as_mut()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn templates_replace_arrays() {
+        let mut table: toml::Table = toml::from_str(
+            r#"
+            [templates.stick]
+            axes = ["left_x", "left_y"]
+            radius = 20
+            [templates.history]
+            buttons = ["a", "b"]
+
+            [[sticks]]
+            template = "stick"
+            axes = ["right_x", "right_y"]
+            pos = [0, 0]
+            [[history]]
+            template = "history"
+            buttons = ["x"]
+            pos = [0, 0]
+            "#,
+        )
+        .unwrap();
+        apply_templates(&mut table).unwrap();
+        let name = |n: &str| Id::Name(n.to_owned());
+        let config: Gamepad = table.try_into().unwrap();
+        assert_eq!(config.sticks[0].axes, (name("right_x"), name("right_y")));
+        assert_eq!(config.sticks[0].radius, Some(20.0));
+        assert_eq!(config.history[0].buttons, Some(vec![name("x")]));
+    }
+
+    #[test]
+    fn includes_concatenate_elements() {
+        let mut base: toml::Table = toml::from_str(
+            r#"
+            stick_radius = 10
+            buttons = [{ id = "a", pos = [0, 0] }]
+            [disconnected]
+            opacity = 0.5
+            "#,
+        )
+        .unwrap();
+        let over: toml::Table = toml::from_str(
+            r#"
+            stick_radius = 20
+            buttons = [{ id = "b", pos = [10, 0] }]
+            [disconnected]
+            inactive = '#000'
+            "#,
+        )
+        .unwrap();
+        merge_layout(&mut base, over);
+        let name = |n: &str| Id::Name(n.to_owned());
+        let config: Gamepad = base.try_into().unwrap();
+        assert_eq!(config.stick_radius, 20.0);
+        let ids: Vec<_> = config.buttons.iter().map(|b| b.id.clone()).collect();
+        assert_eq!(ids, [name("a"), name("b")]);
+        let disconnected = config.disconnected.unwrap();
+        assert_eq!(disconnected.opacity, Some(0.5));
+        assert!(disconnected.inactive.is_some());
+    }
 }
@@ -0,0 +1,254 @@
+//! SDL's `gamecontrollerdb.txt` format, which says where the standard inputs are on
+//! specific models of controller, see <https://github.com/mdqinc/SDL_GameControllerDB>.
+//! Each line is a device GUID, a name, then `input:element` pairs like
+//!
+//! `030000005e0400008e02000010010000,Xbox 360 Controller,a:b0,leftx:a0,dpup:h0.1,...`
+//!
+//! where `bN` is a button, `aN` an axis (`+aN`/`-aN` for half of one, and a trailing
+//! `~` to invert it), and `hN.M` a direction of a hat.
+
+use std::{
+    collections::HashMap,
+    env,
+    sync::{Arc, LazyLock},
+};
+
+use log::warn;
+
+/// A handful of common controllers on linux, point a layout's `mappings` at the full
+/// database for anything else
+const BUNDLED: &str = include_str!("gamecontrollerdb.txt");
+
+#[cfg(target_os = "linux")]
+const PLATFORM: &str = "Linux";
+#[cfg(target_os = "windows")]
+const PLATFORM: &str = "Windows";
+#[cfg(target_os = "macos")]
+const PLATFORM: &str = "Mac OS X";
+#[cfg(not(any(target_os = "linux", target_os = "windows", target_os = "macos")))]
+const PLATFORM: &str = "";
+
+/// The layout's names for inputs that SDL calls something else. Anything that isn't in
+/// here gets looked up as-is, so SDL's own names (like `paddle1`) work too.
+#[rustfmt::skip]
+const NAMES: &[(&str, &str)] = &[
+    ("south", "a"), ("east", "b"), ("west", "x"), ("north", "y"),
+    ("select", "back"), ("mode", "guide"),
+    ("left_bumper", "leftshoulder"), ("right_bumper", "rightshoulder"),
+    ("left_trigger", "lefttrigger"), ("right_trigger", "righttrigger"),
+    ("left_thumb", "leftstick"), ("right_thumb", "rightstick"),
+    ("dpad_up", "dpup"), ("dpad_down", "dpdown"),
+    ("dpad_left", "dpleft"), ("dpad_right", "dpright"),
+    ("left_x", "leftx"), ("left_y", "lefty"), ("right_x", "rightx"), ("right_y", "righty"),
+];
+
+/// Mappings keyed by device GUID
+#[derive(Debug, Default)]
+pub struct Database {
+    mappings: HashMap<[u8; 16], Mapping>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Mapping {
+    pub name: String,
+    inputs: HashMap<String, Element>,
+}
+
+/// One of the device's inputs, numbered the way SDL numbers them
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Element {
+    Button(usize),
+    Axis {
+        index: usize,
+        range: Range,
+        invert: bool,
+    },
+    /// a direction of a hat, 1 is up, 2 right, 4 down, and 8 left
+    Hat {
+        index: usize,
+        mask: u8,
+    },
+}
+
+/// Which part of an axis an input uses
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Range {
+    #[default]
+    Full,
+    Positive,
+    Negative,
+}
+
+impl Database {
+    /// The bundled mappings, then `text`, then anything in `SDL_GAMECONTROLLERCONFIG`,
+    /// with later mappings replacing earlier ones for the same device
+    pub fn new(text: Option<&str>) -> Self {
+        let mut db = Self::default();
+        db.add(BUNDLED);
+        if let Some(text) = text {
+            db.add(text);
+        }
+        if let Ok(text) = env::var("SDL_GAMECONTROLLERCONFIG") {
+            db.add(&text);
+        }
+        db
+    }
+
+    /// [`Database::new`] without any extra mappings, which is only parsed once
+    pub fn bundled() -> Arc<Self> {
+        static BUNDLED_DB: LazyLock<Arc<Database>> =
+            LazyLock::new(|| Arc::new(Database::new(None)));
+        BUNDLED_DB.clone()
+    }
+
+    /// Skips comments and mappings for other platforms
+    pub fn add(&mut self, text: &str) {
+        for line in text.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            match parse_line(line) {
+                Some((guid, Some(mapping))) => {
+                    self.mappings.insert(guid, mapping);
+                }
+                Some((_, None)) => {}
+                None => warn!("Couldn't parse controller mapping: {line}"),
+            }
+        }
+    }
+
+    /// Falls back to ignoring the version and name checksum in the GUID like SDL does,
+    /// since those change with firmware and drivers
+    pub fn get(&self, guid: [u8; 16]) -> Option<&Mapping> {
+        self.mappings.get(&guid).or_else(|| {
+            let guid = loose(guid);
+            self.mappings.iter().find(|&(&g, _)| loose(g) == guid).map(|(_, m)| m)
+        })
+    }
+}
+
+impl Mapping {
+    /// Looks up one of the layout's names (or SDL's)
+    pub fn get(&self, name: &str) -> Option<Element> {
+        let name = NAMES.iter().find(|(n, _)| *n == name).map_or(name, |&(_, sdl)| sdl);
+        self.inputs.get(name).copied()
+    }
+}
+
+/// Zeroes the name checksum and version
+fn loose(mut guid: [u8; 16]) -> [u8; 16] {
+    guid[2..4].fill(0);
+    guid[12..14].fill(0);
+    guid
+}
+
+fn parse_guid(hex: &str) -> Option<[u8; 16]> {
+    let mut guid = [0; 16];
+    if hex.len() != 32 {
+        return None;
+    }
+    for (i, byte) in guid.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(hex.get(i * 2..i * 2 + 2)?, 16).ok()?;
+    }
+    Some(guid)
+}
+
+fn parse_element(s: &str) -> Option<Element> {
+    let (range, s) = match (s.strip_prefix('+'), s.strip_prefix('-')) {
+        (Some(s), _) => (Range::Positive, s),
+        (_, Some(s)) => (Range::Negative, s),
+        _ => (Range::Full, s),
+    };
+    let (s, invert) = s.strip_suffix('~').map_or((s, false), |s| (s, true));
+    if let Some(i) = s.strip_prefix('b') {
+        Some(Element::Button(i.parse().ok()?))
+    } else if let Some(i) = s.strip_prefix('a') {
+        Some(Element::Axis { index: i.parse().ok()?, range, invert })
+    } else {
+        let (index, mask) = s.strip_prefix('h')?.split_once('.')?;
+        Some(Element::Hat { index: index.parse().ok()?, mask: mask.parse().ok()? })
+    }
+}
+
+/// The mapping is `None` if it's for another platform. Fields that aren't inputs (like
+/// SDL's hints) are ignored.
+fn parse_line(line: &str) -> Option<([u8; 16], Option<Mapping>)> {
+    let mut fields = line.split(',');
+    let guid = parse_guid(fields.next()?)?;
+    let name = fields.next()?.to_owned();
+    let mut inputs = HashMap::new();
+    for field in fields.filter(|f| !f.is_empty()) {
+        let (key, value) = field.split_once(':')?;
+        if key == "platform" {
+            if value != PLATFORM {
+                return Some((guid, None));
+            }
+        } else if let Some(element) = parse_element(value) {
+            inputs.insert(key.to_owned(), element);
+        }
+    }
+    Some((guid, Some(Mapping { name, inputs })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GUID: &str = "030000005e0400008e02000010010000";
+
+    #[test]
+    fn elements() {
+        let axis = |index, range, invert| Some(Element::Axis { index, range, invert });
+        assert_eq!(parse_element("b3"), Some(Element::Button(3)));
+        assert_eq!(parse_element("a2"), axis(2, Range::Full, false));
+        assert_eq!(parse_element("a2~"), axis(2, Range::Full, true));
+        assert_eq!(parse_element("+a1"), axis(1, Range::Positive, false));
+        assert_eq!(parse_element("-a5~"), axis(5, Range::Negative, true));
+        assert_eq!(parse_element("h0.4"), Some(Element::Hat { index: 0, mask: 4 }));
+        for bad in ["", "b", "ax", "h0", "h.1", "x1", "+b"] {
+            assert_eq!(parse_element(bad), None, "{bad:?}");
+        }
+    }
+
+    #[test]
+    fn lines() {
+        let line =
+            format!("{GUID},Xbox 360 Controller,a:b0,leftx:a0,dpup:h0.1,righty:a4~,");
+        let (guid, mapping) = parse_line(&line).unwrap();
+        assert_eq!(guid, parse_guid(GUID).unwrap());
+        let mapping = mapping.unwrap();
+        assert_eq!(mapping.name, "Xbox 360 Controller");
+        // the layout's names and SDL's both work
+        assert_eq!(mapping.get("south"), Some(Element::Button(0)));
+        assert_eq!(mapping.get("a"), Some(Element::Button(0)));
+        assert_eq!(mapping.get("dpad_up"), Some(Element::Hat { index: 0, mask: 1 }));
+        let right_y = Element::Axis { index: 4, range: Range::Full, invert: true };
+        assert_eq!(mapping.get("right_y"), Some(right_y));
+        assert_eq!(mapping.get("north"), None);
+
+        let other_platform = format!("{GUID},Xbox 360 Controller,a:b0,platform:Android,");
+        assert!(parse_line(&other_platform).unwrap().1.is_none());
+        // hints and anything else that isn't an input get skipped
+        let hint = format!("{GUID},Pad,a:b0,hint:!SDL_GAMECONTROLLER_USE_BUTTON_LABELS:=1");
+        assert_eq!(parse_line(&hint).unwrap().1.unwrap().inputs.len(), 1);
+
+        assert!(parse_line("0300,Too Short,a:b0").is_none());
+        assert!(parse_line(&format!("{GUID},No Colon,a")).is_none());
+        assert!(parse_line(GUID).is_none());
+    }
+
+    #[test]
+    fn loose_guids() {
+        let mut db = Database::default();
+        db.add(&format!("# a comment\n{GUID},Exact,a:b0\n\n"));
+        // the same vendor and product, but another name checksum and version
+        db.add("030012340100000002000000abcd0000,Loose,a:b1");
+
+        let guid = |hex| parse_guid(hex).unwrap();
+        assert_eq!(db.get(guid(GUID)).unwrap().name, "Exact");
+        assert_eq!(db.get(guid("03000000010000000200000000000000")).unwrap().name, "Loose");
+        assert_eq!(db.get(guid("0300ffff0100000002000000ffff0000")).unwrap().name, "Loose");
+        // a different product
+        assert!(db.get(guid("03000000010000000300000000000000")).is_none());
+    }
+}
@@ -0,0 +1,272 @@
+use std::{
+    fs::{self, File},
+    os::unix::fs::FileExt,
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use color_eyre::{
+    Report,
+    eyre::{Context, eyre},
+};
+use log::{info, warn};
+
+use crate::{
+    gamepad::{Backend, InputState, Inputs, MissingId},
+    melee::{self, Controller, Mapping},
+};
+
+/// Emulated MEM1 is always 24MiB, but dolphin maps it in a 32MiB region
+const MEM1_SIZE: u64 = 0x2000000;
+/// Where MEM1 starts in the gamecube's address space
+const MEM1_START: u32 = 0x80000000;
+/// The per-port pad status that melee (NTSC 1.02) keeps after polling the controllers
+const PAD_STATUS: u32 = 0x804C1FAC;
+const PAD_STATUS_LEN: u32 = 0x44;
+const RETRY_DELAY: Duration = Duration::from_secs(1);
+
+#[derive(Debug)]
+pub struct Dolphin {
+    port: u8,
+    process: Option<Process>,
+    last_attempt: Instant,
+    mapping: Mapping,
+    plugged_in: bool,
+}
+
+#[derive(Debug)]
+struct Process {
+    pid: u32,
+    mem: File,
+    mem1: u64,
+}
+
+impl Process {
+    /// Finds the first running dolphin and the address that it mapped MEM1 to
+    fn find() -> Result<Self, Report> {
+        let pid = fs::read_dir("/proc")?
+            .filter_map(|e| e.ok()?.file_name().to_str()?.parse().ok())
+            .find(|&pid| is_dolphin(pid))
+            .ok_or(eyre!("couldn't find a running dolphin process"))?;
+        Self::attach(pid)
+    }
+
+    fn attach(pid: u32) -> Result<Self, Report> {
+        let maps = fs::read_to_string(format!("/proc/{pid}/maps"))
+            .with_context(|| format!("couldn't read memory maps of process {pid}"))?;
+        let mem1 = maps
+            .lines()
+            .find_map(mem1_mapping)
+            .ok_or(eyre!("process {pid} doesn't have emulated memory mapped"))?;
+        let mem = File::open(format!("/proc/{pid}/mem")).with_context(|| {
+            format!(
+                "couldn't open memory of {pid}, check /proc/sys/kernel/yama/ptrace_scope"
+            )
+        })?;
+        let process = Self { pid, mem, mem1 };
+
+        let mut game = [0u8; 6];
+        process.read(MEM1_START, &mut game)?;
+        if &game != b"GALE01" {
+            warn!("dolphin is running {}, not melee", String::from_utf8_lossy(&game));
+        }
+        Ok(process)
+    }
+
+    fn read(&self, addr: u32, buf: &mut [u8]) -> Result<(), Report> {
+        let offset = addr.checked_sub(MEM1_START).filter(|&o| (o as u64) < MEM1_SIZE);
+        let offset = offset.ok_or(eyre!("{addr:#x} is outside of MEM1"))?;
+        self.mem
+            .read_exact_at(buf, self.mem1 + offset as u64)
+            .with_context(|| format!("couldn't read memory of process {}", self.pid))
+    }
+
+    /// also returns whether or not the controller is plugged in
+    fn controller(&self, port: u8) -> Result<(Controller, bool), Report> {
+        let mut buf = [0u8; PAD_STATUS_LEN as usize];
+        self.read(PAD_STATUS + (port as u32 - 1) * PAD_STATUS_LEN, &mut buf)?;
+        let u32_at = |i: usize| u32::from_be_bytes(buf[i..i + 4].try_into().unwrap());
+        let f32_at = |i: usize| f32::from_bits(u32_at(i));
+        // this is the error code from polling the controller, -1 means it's unplugged
+        let plugged_in = buf[0x41] == 0;
+        let controller = Controller {
+            buttons: u32_at(0x00) as u64,
+            analog: [
+                f32_at(0x20),
+                // melee has up as positive
+                -f32_at(0x24),
+                f32_at(0x28),
+                -f32_at(0x2C),
+                f32_at(0x30),
+                f32_at(0x34),
+            ],
+        };
+        Ok((controller, plugged_in))
+    }
+}
+
+fn is_dolphin(pid: u32) -> bool {
+    let name = |file| fs::read_to_string(format!("/proc/{pid}/{file}")).unwrap_or_default();
+    let cmdline = name("cmdline");
+    let exe = cmdline.split('\0').next().unwrap_or_default();
+    let exe = Path::new(exe).file_name().unwrap_or_default().to_string_lossy();
+    [name("comm").trim(), &exe].iter().any(|n| n.to_lowercase().contains("dolphin"))
+}
+
+/// Parses a line of /proc/<pid>/maps and returns its start address if it's a shared
+/// mapping the size of emulated MEM1, like:
+/// `7f3c00000000-7f3c02000000 rw-s 00000000 00:01 1234 /memfd:dolphin-emu.4321 (deleted)`
+fn mem1_mapping(line: &str) -> Option<u64> {
+    let mut fields = line.split_whitespace();
+    let (start, end) = fields.next()?.split_once('-')?;
+    let perms = fields.next()?;
+    let offset = fields.next()?;
+    let start = u64::from_str_radix(start, 16).ok()?;
+    let end = u64::from_str_radix(end, 16).ok()?;
+    (end.checked_sub(start) == Some(MEM1_SIZE)
+        && perms.starts_with('r')
+        && perms.ends_with('s')
+        && u64::from_str_radix(offset, 16).ok()? == 0)
+        .then_some(start)
+}
+
+impl Backend for Dolphin {
+    /// controller port, from 1 to 4
+    type InitState = u8;
+    type Err = Report;
+
+    fn init(port: Self::InitState, inputs: &Inputs) -> Result<Self, Self::Err> {
+        if !(1..=4).contains(&port) {
+            return Err(eyre!("port {port} doesn't exist, pick one from 1 to 4"));
+        }
+        // start disconnected if dolphin isn't open yet, `poll` keeps looking for it
+        let process = Process::find()
+            .inspect(|p| info!("found dolphin (pid {}) with MEM1 at {:#x}", p.pid, p.mem1))
+            .inspect_err(|e| warn!("{e}, waiting for it to start"))
+            .ok();
+        Ok(Self {
+            port,
+            process,
+            last_attempt: Instant::now(),
+            mapping: Mapping::new(inputs, melee::BUTTONS),
+            plugged_in: false,
+        })
+    }
+
+    fn poll(&mut self, state: &mut InputState) -> bool {
+        if self.process.is_none() && self.last_attempt.elapsed() > RETRY_DELAY {
+            self.last_attempt = Instant::now();
+            self.process = Process::find().ok();
+            if let Some(p) = &self.process {
+                info!("attached to dolphin (pid {})", p.pid);
+            }
+        }
+        let Some(process) = &self.process else { return false };
+        match process.controller(self.port) {
+            Ok((controller, plugged_in)) => {
+                self.plugged_in = plugged_in;
+                self.mapping.apply(&controller, state)
+            }
+            Err(e) => {
+                warn!("lost dolphin: {e}");
+                self.process = None;
+                false
+            }
+        }
+    }
+
+    fn connected(&self) -> bool {
+        self.process.is_some() && self.plugged_in
+    }
+
+    fn missing_ids(&self) -> &[MissingId] {
+        &self.mapping.missing
+    }
+
+    fn reload(&mut self, inputs: &Inputs) {
+        self.mapping = Mapping::new(inputs, melee::BUTTONS);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ptr;
+
+    use super::*;
+    use crate::config;
+
+    #[test]
+    fn parses_maps_lines() {
+        let mem1 = "7f3c00000000-7f3c02000000 rw-s 00000000 00:01 1234 /memfd:dolphin-emu";
+        assert_eq!(mem1_mapping(mem1), Some(0x7f3c00000000));
+        // private, the wrong size, and not from the start of the file
+        assert_eq!(mem1_mapping("7f3c00000000-7f3c02000000 rw-p 00000000 00:01 1"), None);
+        assert_eq!(mem1_mapping("7f3c00000000-7f3c01000000 rw-s 00000000 00:01 1"), None);
+        assert_eq!(mem1_mapping("7f3c00000000-7f3c02000000 rw-s 00001000 00:01 1"), None);
+        // backwards
+        assert_eq!(mem1_mapping("7f3c02000000-7f3c00000000 rw-s 00000000 00:01 1"), None);
+        assert_eq!(mem1_mapping("garbage"), None);
+    }
+
+    /// Maps a region like dolphin's MEM1 in this process, with melee's pad status for
+    /// port 2 filled in, and reads it back like it's dolphin
+    #[test]
+    fn reads_a_fake_dolphin() {
+        let size = MEM1_SIZE as usize;
+        // SAFETY: a fresh anonymous mapping that nothing else uses, and it's only
+        // written to inside its bounds
+        let mem1 = unsafe {
+            let mem1 = libc::mmap(
+                ptr::null_mut(),
+                size,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_SHARED | libc::MAP_ANONYMOUS,
+                -1,
+                0,
+            );
+            assert_ne!(mem1, libc::MAP_FAILED);
+            std::slice::from_raw_parts_mut(mem1 as *mut u8, size)
+        };
+        mem1[..6].copy_from_slice(b"GALE01");
+        let pad = (PAD_STATUS - MEM1_START + PAD_STATUS_LEN) as usize;
+        // A and start, main stick right and up, and the L trigger half way
+        mem1[pad..pad + 4].copy_from_slice(&(1u32 << 8 | 1 << 12).to_be_bytes());
+        mem1[pad + 0x20..pad + 0x24].copy_from_slice(&1f32.to_be_bytes());
+        mem1[pad + 0x24..pad + 0x28].copy_from_slice(&0.5f32.to_be_bytes());
+        mem1[pad + 0x30..pad + 0x34].copy_from_slice(&0.5f32.to_be_bytes());
+
+        let layout: config::Gamepad = toml::from_str(
+            r#"
+            buttons = [
+                { id = "a", pos = [0, 0] },
+                { id = "b", pos = [50, 0] },
+                { id = "start", pos = [100, 0] },
+            ]
+            sticks = [{ axes = ["left_x", "left_y"], pos = [50, 100] }]
+            axes = [{ id = "l", pos = [200, 0] }]
+            "#,
+        )
+        .unwrap();
+        let inputs: Inputs = (&layout).into();
+        let mut dolphin = Dolphin {
+            port: 2,
+            process: Some(Process::attach(std::process::id()).unwrap()),
+            last_attempt: Instant::now(),
+            mapping: Mapping::new(&inputs, melee::BUTTONS),
+            plugged_in: false,
+        };
+        let mut state: InputState = (&inputs).into();
+        assert!(dolphin.poll(&mut state));
+        assert!(dolphin.connected());
+        assert_eq!(state.buttons, [true, false, true]);
+        assert_eq!(state.sticks, [(1.0, -0.5)]);
+        assert_eq!(state.axes, [0.5]);
+
+        // unplugged
+        mem1[pad + 0x41] = 0xff;
+        dolphin.poll(&mut state);
+        assert!(!dolphin.connected());
+        // SAFETY: nothing uses the mapping after this
+        unsafe { libc::munmap(mem1.as_mut_ptr().cast(), size) };
+    }
+}
@@ -0,0 +1,548 @@
+//! Moving and resizing elements in the standalone window, and saving them back to the
+//! layout without messing up its comments or formatting
+//!
+//! E toggles edit mode, then click to select something and drag it around. The arrow
+//! keys nudge it (10px with shift), = and - resize it (only the width with shift, only
+//! the height with ctrl), and ctrl+S saves.
+
+use std::{fs, path::Path};
+
+use color_eyre::{
+    Report,
+    eyre::{Context, eyre},
+};
+use log::{error, info, warn};
+use minifb::{Key, KeyRepeat, MouseButton, MouseMode, Window};
+use tiny_skia::{Color, Paint, PathBuilder, Pixmap, Rect, Stroke, StrokeDash, Transform};
+use toml_edit::{Array, DocumentMut, InlineTable, Item, TableLike, Value};
+
+use crate::config::{self, Shape};
+use crate::gamepad::Inputs;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Element {
+    Button(usize),
+    Stick(usize),
+    Axis(usize),
+    Dpad(usize),
+}
+
+impl Element {
+    /// The array that it's in, in the layout file
+    fn key(self) -> &'static str {
+        match self {
+            Element::Button(_) => "buttons",
+            Element::Stick(_) => "sticks",
+            Element::Axis(_) => "axes",
+            Element::Dpad(_) => "dpads",
+        }
+    }
+
+    fn index(self) -> usize {
+        match self {
+            Element::Button(i)
+            | Element::Stick(i)
+            | Element::Axis(i)
+            | Element::Dpad(i) => i,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Editor {
+    pub enabled: bool,
+    /// the layout along with any changes that haven't been saved
+    pub config: config::Gamepad,
+    /// what's on disk, so that only the elements that changed get rewritten
+    saved: config::Gamepad,
+    pub selected: Option<Element>,
+    /// where the mouse grabbed the selected element relative to its pos, and the
+    /// image's origin at the time so it doesn't shift if the layout grows mid-drag
+    grab: Option<((f32, f32), (f32, f32))>,
+}
+
+impl Editor {
+    pub fn new(config: config::Gamepad) -> Self {
+        Self { enabled: false, saved: config.clone(), config, selected: None, grab: None }
+    }
+
+    /// Takes the layout from disk, throwing away anything that wasn't saved
+    pub fn reload(&mut self, config: config::Gamepad) {
+        self.config = config.clone();
+        self.saved = config;
+        if self.selected.is_some_and(|el| self.bounds(el).is_none()) {
+            self.selected = None;
+        }
+    }
+
+    /// For the window title
+    pub fn status(&self) -> &'static str {
+        match (self.enabled, self.elements().any(|el| self.changed(el))) {
+            (false, _) => "",
+            (true, false) => " (editing)",
+            (true, true) => " (editing, unsaved)",
+        }
+    }
+
+    /// Handles the mouse and keyboard, and returns whether the layout changed
+    pub fn update(&mut self, window: &Window, inputs: &Inputs, path: &Path) -> bool {
+        let shift =
+            window.is_key_down(Key::LeftShift) || window.is_key_down(Key::RightShift);
+        let ctrl = window.is_key_down(Key::LeftCtrl) || window.is_key_down(Key::RightCtrl);
+        if ctrl && window.is_key_pressed(Key::S, KeyRepeat::No) {
+            match self.save(path) {
+                Ok(()) => info!("Saved {}", path.display()),
+                Err(e) => error!("Couldn't save: {e:#}"),
+            }
+        }
+
+        let mouse = window.get_mouse_pos(MouseMode::Discard);
+        let mut changed = false;
+        match (window.get_mouse_down(MouseButton::Left), self.grab, mouse) {
+            (true, None, Some((x, y))) => {
+                let (x, y) = (x + inputs.origin.0, y + inputs.origin.1);
+                self.selected = self.element_at(x, y);
+                self.grab = self.selected.map(|el| {
+                    let (px, py) = *self.pos_mut(el);
+                    ((x - px, y - py), inputs.origin)
+                });
+            }
+            (true, Some(((dx, dy), (ox, oy))), Some((x, y))) => {
+                if let Some(el) = self.selected {
+                    let pos = self.pos_mut(el);
+                    let new = ((x + ox - dx).round(), (y + oy - dy).round());
+                    changed = *pos != new;
+                    *pos = new;
+                }
+            }
+            (false, ..) => self.grab = None,
+            _ => {}
+        }
+
+        let Some(el) = self.selected else { return changed };
+        let step = if shift { 10.0 } else { 1.0 };
+        let pressed = |key| window.is_key_pressed(key, KeyRepeat::Yes);
+        let nudge = [
+            (Key::Left, (-step, 0.0)),
+            (Key::Right, (step, 0.0)),
+            (Key::Up, (0.0, -step)),
+            (Key::Down, (0.0, step)),
+        ];
+        for (key, (dx, dy)) in nudge {
+            if pressed(key) {
+                let pos = self.pos_mut(el);
+                *pos = (pos.0 + dx, pos.1 + dy);
+                changed = true;
+            }
+        }
+        for (key, d) in [(Key::Equal, 1.0), (Key::Minus, -1.0)] {
+            if pressed(key) {
+                let (dw, dh) = match (shift, ctrl) {
+                    (true, false) => (d, 0.0),
+                    (false, true) => (0.0, d),
+                    _ => (d, d),
+                };
+                self.resize(el, dw, dh);
+                changed = true;
+            }
+        }
+        changed
+    }
+
+    /// Outlines the selected element
+    pub fn draw(&self, img: &mut Pixmap, inputs: &Inputs) {
+        if !self.enabled {
+            return;
+        }
+        let Some(bounds) = self.selected.and_then(|el| self.bounds(el)) else { return };
+        let t = Transform::from_translate(-inputs.origin.0, -inputs.origin.1);
+        let mut paint = Paint { anti_alias: true, ..Default::default() };
+        paint.set_color(Color::from_rgba8(0xff, 0x00, 0xff, 0xff));
+        let stroke = Stroke {
+            width: 1.0,
+            dash: StrokeDash::new(vec![4.0, 4.0], 0.0),
+            ..Default::default()
+        };
+        img.stroke_path(&PathBuilder::from_rect(bounds), &paint, &stroke, t, None);
+    }
+
+    fn elements(&self) -> impl Iterator<Item = Element> + use<> {
+        let c = &self.config;
+        (0..c.buttons.len())
+            .map(Element::Button)
+            .chain((0..c.sticks.len()).map(Element::Stick))
+            .chain((0..c.axes.len()).map(Element::Axis))
+            .chain((0..c.dpads.len()).map(Element::Dpad))
+    }
+
+    /// In the layout's coordinates
+    fn bounds(&self, el: Element) -> Option<Rect> {
+        let c = &self.config;
+        Some(match el {
+            Element::Button(i) => c.buttons.get(i)?.load(c).bounds(),
+            Element::Stick(i) => c.sticks.get(i)?.load(c).bounds(),
+            Element::Axis(i) => c.axes.get(i)?.load(c).bounds(),
+            Element::Dpad(i) => c.dpads.get(i)?.load(c).bounds(),
+        })
+    }
+
+    /// The smallest element under the cursor, so buttons on top of a background shape
+    /// can still be picked
+    fn element_at(&self, x: f32, y: f32) -> Option<Element> {
+        self.elements()
+            .filter_map(|el| self.bounds(el).map(|b| (el, b)))
+            .filter(|(_, b)| {
+                b.left() <= x && x <= b.right() && b.top() <= y && y <= b.bottom()
+            })
+            .min_by(|(_, a), (_, b)| {
+                (a.width() * a.height()).total_cmp(&(b.width() * b.height()))
+            })
+            .map(|(el, _)| el)
+    }
+
+    fn pos_mut(&mut self, el: Element) -> &mut (f32, f32) {
+        let c = &mut self.config;
+        match el {
+            Element::Button(i) => &mut c.buttons[i].pos,
+            Element::Stick(i) => &mut c.sticks[i].pos,
+            Element::Axis(i) => &mut c.axes[i].pos,
+            Element::Dpad(i) => &mut c.dpads[i].pos,
+        }
+    }
+
+    fn resize(&mut self, el: Element, dw: f32, dh: f32) {
+        let c = &mut self.config;
+        match el {
+            Element::Button(i) => {
+                let shape =
+                    c.buttons[i].shape.get_or_insert_with(|| c.button_shape.clone());
+                resize_shape(shape, dw, dh);
+            }
+            Element::Axis(i) => {
+                let shape = c.axes[i].shape.get_or_insert_with(|| c.axis_shape.clone());
+                resize_shape(shape, dw, dh);
+            }
+            Element::Stick(i) => {
+                let radius = c.sticks[i].radius.unwrap_or(c.stick_radius);
+                c.sticks[i].radius = Some((radius + dw.max(dh)).max(1.0));
+            }
+            Element::Dpad(i) => {
+                let size = c.dpads[i].size.unwrap_or(c.dpad_size);
+                c.dpads[i].size = Some((size + dw.max(dh)).max(1.0));
+            }
+        }
+    }
+
+    /// Whether anything that the editor can touch is different from what's on disk
+    fn changed(&self, el: Element) -> bool {
+        let (c, s) = (&self.config, &self.saved);
+        match el {
+            Element::Button(i) => {
+                let (a, b) = (&c.buttons[i], &s.buttons[i]);
+                a.pos != b.pos || a.shape != b.shape
+            }
+            Element::Stick(i) => {
+                let (a, b) = (&c.sticks[i], &s.sticks[i]);
+                a.pos != b.pos || a.radius != b.radius
+            }
+            Element::Axis(i) => {
+                let (a, b) = (&c.axes[i], &s.axes[i]);
+                a.pos != b.pos || a.shape != b.shape
+            }
+            Element::Dpad(i) => {
+                let (a, b) = (&c.dpads[i], &s.dpads[i]);
+                a.pos != b.pos || a.size != b.size
+            }
+        }
+    }
+
+    /// Rewrites the values that changed in place, leaving the rest of the file alone.
+    /// Anything that couldn't be written stays unsaved.
+    pub fn save(&mut self, path: &Path) -> Result<(), Report> {
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("Couldn't read {}", path.display()))?;
+        let mut doc: DocumentMut =
+            text.parse().with_context(|| format!("Couldn't parse {}", path.display()))?;
+        let changed: Vec<_> = self.elements().filter(|&el| self.changed(el)).collect();
+        let mut saved = self.saved.clone();
+        for el in changed {
+            // included elements come first, and belong to some other file
+            let included = self.config.included.get(el.key()).copied().unwrap_or_default();
+            let Some(index) = el.index().checked_sub(included) else {
+                warn!(
+                    "{} {} comes from an included file, so it wasn't saved",
+                    el.key(),
+                    el.index()
+                );
+                continue;
+            };
+            let table = element_table(&mut doc, el.key(), index).ok_or_else(|| {
+                eyre!("Couldn't find {} {index} in {}", el.key(), path.display())
+            })?;
+            // only what changed gets written, so values from templates stay there
+            let (c, s) = (&self.config, &mut saved);
+            match el {
+                Element::Button(i) => {
+                    let (a, b) = (&c.buttons[i], &mut s.buttons[i]);
+                    save_pos(table, a.pos, &mut b.pos);
+                    save_shape(table, &a.shape, &mut b.shape);
+                }
+                Element::Stick(i) => {
+                    let (a, b) = (&c.sticks[i], &mut s.sticks[i]);
+                    save_pos(table, a.pos, &mut b.pos);
+                    if a.radius != b.radius
+                        && let Some(radius) = a.radius
+                        && set(table, "radius", number(radius))
+                    {
+                        b.radius = a.radius;
+                    }
+                }
+                Element::Axis(i) => {
+                    let (a, b) = (&c.axes[i], &mut s.axes[i]);
+                    save_pos(table, a.pos, &mut b.pos);
+                    save_shape(table, &a.shape, &mut b.shape);
+                }
+                Element::Dpad(i) => {
+                    let (a, b) = (&c.dpads[i], &mut s.dpads[i]);
+                    save_pos(table, a.pos, &mut b.pos);
+                    if a.size != b.size
+                        && let Some(size) = a.size
+                        && set(table, "size", number(size))
+                    {
+                        b.size = a.size;
+                    }
+                }
+            }
+        }
+        fs::write(path, doc.to_string())
+            .with_context(|| format!("Couldn't write {}", path.display()))?;
+        self.saved = saved;
+        Ok(())
+    }
+}
+
+fn save_pos(table: &mut dyn TableLike, pos: (f32, f32), saved: &mut (f32, f32)) {
+    if pos != *saved && set(table, "pos", point(pos)) {
+        *saved = pos;
+    }
+}
+
+fn save_shape(table: &mut dyn TableLike, shape: &Option<Shape>, saved: &mut Option<Shape>) {
+    if shape != saved
+        && let Some(new) = shape
+        && set_shape(table, new)
+    {
+        *saved = shape.clone();
+    }
+}
+
+fn resize_shape(shape: &mut Shape, dw: f32, dh: f32) {
+    match shape {
+        Shape::Circle { radius } => *radius = (*radius + dw.max(dh)).max(1.0),
+        Shape::RoundedRect { size, .. } => {
+            *size = ((size.0 + dw).max(1.0), (size.1 + dh).max(1.0));
+        }
+        Shape::Path { scale, .. } => {
+            *scale = Some((scale.unwrap_or(1.0) + dw.max(dh) * 0.05).max(0.05));
+        }
+        // the drawing decides how big these are
+        Shape::Svg { .. } => {}
+    }
+}
+
+/// Elements can be in an array of tables or an array of inline tables
+fn element_table<'a>(
+    doc: &'a mut DocumentMut,
+    key: &str,
+    index: usize,
+) -> Option<&'a mut dyn TableLike> {
+    match doc.get_mut(key)? {
+        Item::ArrayOfTables(tables) => {
+            tables.get_mut(index).map(|t| t as &mut dyn TableLike)
+        }
+        Item::Value(Value::Array(array)) => {
+            array.get_mut(index)?.as_inline_table_mut().map(|t| t as &mut dyn TableLike)
+        }
+        _ => None,
+    }
+}
+
+/// Returns whether the whole shape got written
+fn set_shape(table: &mut dyn TableLike, shape: &Shape) -> bool {
+    if !table.contains_key("shape") {
+        set(table, "shape", Value::InlineTable(InlineTable::new()));
+    }
+    let Some(table) = table.get_mut("shape").and_then(Item::as_table_like_mut) else {
+        warn!("shape isn't a table, so it wasn't changed");
+        return false;
+    };
+    let mut set = |key, value| set(table, key, value);
+    match shape {
+        Shape::Circle { radius } => set("radius", number(*radius)),
+        Shape::RoundedRect { size, radius } => {
+            let size = set("size", point(*size));
+            radius.is_none_or(|r| set("radius", number(r))) && size
+        }
+        Shape::Path { d, scale, rotation } => {
+            let d = set("d", d.as_str().into());
+            let scale = scale.is_none_or(|s| set("scale", number(s)));
+            rotation.is_none_or(|r| set("rotation", number(r))) && d && scale
+        }
+        Shape::Svg { svg, id, .. } => {
+            let svg = set("svg", svg.to_string_lossy().as_ref().into());
+            set("id", id.as_str().into()) && svg
+        }
+    }
+}
+
+/// Replaces a value but keeps the whitespace and comments around it, and leaves it
+/// alone if it's the same number written differently. Values that use `$vars` are left
+/// alone too, since there's no telling how the new number should be written with them,
+/// and those return false.
+fn set(table: &mut dyn TableLike, key: &str, mut new: Value) -> bool {
+    match table.get_mut(key).and_then(Item::as_value_mut) {
+        Some(old) if same(old, &new) => {}
+        Some(old) if uses_vars(old) => {
+            warn!(
+                "{key} = {} uses vars, so it wasn't changed to {new}",
+                old.to_string().trim()
+            );
+            return false;
+        }
+        Some(old) => {
+            let decor = old.decor().clone();
+            *old = new;
+            *old.decor_mut() = decor;
+        }
+        None => {
+            // in inline tables the space before the closing brace belongs to the last value
+            if let Some((_, Item::Value(last))) = table.iter_mut().last()
+                && let Some(suffix) = last.decor().suffix().cloned()
+                && suffix.as_str().is_some_and(|s| s.trim().is_empty())
+            {
+                last.decor_mut().set_suffix("");
+                new.decor_mut().set_suffix(suffix);
+            }
+            table.insert(key, Item::Value(new));
+        }
+    }
+    true
+}
+
+fn same(a: &Value, b: &Value) -> bool {
+    let float = |v: &Value| v.as_float().or(v.as_integer().map(|i| i as f64));
+    match (a, b) {
+        (Value::Array(a), Value::Array(b)) => {
+            a.len() == b.len() && a.iter().zip(b).all(|(a, b)| same(a, b))
+        }
+        (Value::String(a), Value::String(b)) => a.value() == b.value(),
+        _ => float(a).is_some_and(|a| Some(a) == float(b)),
+    }
+}
+
+fn uses_vars(value: &Value) -> bool {
+    match value {
+        Value::String(s) => s.value().replace("$$", "").contains('$'),
+        Value::Array(values) => values.iter().any(uses_vars),
+        Value::InlineTable(table) => table.iter().any(|(_, v)| uses_vars(v)),
+        _ => false,
+    }
+}
+
+/// Whole numbers get written as integers
+fn number(n: f32) -> Value {
+    if n.fract() == 0.0 {
+        (n as i64).into()
+    } else {
+        // f32s have a lot of noise once they're turned into f64s
+        ((n as f64 * 1000.0).round() / 1000.0).into()
+    }
+}
+
+fn point((x, y): (f32, f32)) -> Value {
+    Value::Array(Array::from_iter([number(x), number(y)]))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+
+    #[test]
+    fn keeps_vars() {
+        let path = env::temp_dir().join(format!("editor-{}.toml", std::process::id()));
+        let layout = r#"
+[vars]
+scale = 2
+r = 15
+
+[[buttons]]
+id = 0
+pos = ["100 * $scale", 50] # moved around with vars
+[[buttons]]
+id = 1
+pos = [10, 10]
+shape = { radius = "$r" }
+
+[[sticks]]
+axes = [0, 1]
+pos = [300, 100]
+radius = "$r"
+"#;
+        fs::write(&path, layout).unwrap();
+        let (config, _) = config::Gamepad::from_file(&path).unwrap();
+        let mut editor = Editor::new(config);
+        let c = &mut editor.config;
+        c.buttons[0].pos = (205.0, 50.0);
+        c.buttons[1].pos = (20.0, 10.0);
+        c.buttons[1].shape = Some(Shape::Circle { radius: 20.0 });
+        c.sticks[0].pos = (310.0, 100.0);
+        c.sticks[0].radius = Some(25.0);
+        editor.save(&path).unwrap();
+
+        let saved = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        let expected = layout
+            .replace("pos = [10, 10]", "pos = [20, 10]")
+            .replace("pos = [300, 100]", "pos = [310, 100]");
+        assert_eq!(saved, expected);
+        // the values that weren't written are still unsaved
+        assert_eq!(editor.saved.buttons[1].pos, (20.0, 10.0));
+        assert!(editor.changed(Element::Button(0)));
+        assert!(editor.changed(Element::Button(1)));
+        assert!(editor.changed(Element::Stick(0)));
+    }
+
+    #[test]
+    fn leaves_templates_alone() {
+        let path = env::temp_dir().join(format!("templates-{}.toml", std::process::id()));
+        let layout = r#"
+[templates.big]
+shape = { radius = 30 }
+
+[[buttons]]
+template = "big"
+id = 0
+pos = [50, 50]
+[[buttons]]
+template = "big"
+id = 1
+pos = [150, 50]
+"#;
+        fs::write(&path, layout).unwrap();
+        let (config, _) = config::Gamepad::from_file(&path).unwrap();
+        let mut editor = Editor::new(config);
+        editor.config.buttons[0].pos = (60.0, 50.0);
+        editor.resize(Element::Button(1), 5.0, 5.0);
+        editor.save(&path).unwrap();
+
+        let saved = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        let expected = layout
+            .replace("pos = [50, 50]", "pos = [60, 50]")
+            .replace("pos = [150, 50]\n", "pos = [150, 50]\nshape = { radius = 35 }\n");
+        assert_eq!(saved, expected);
+        assert!(editor.elements().all(|el| !editor.changed(el)));
+    }
+}
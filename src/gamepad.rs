@@ -1,16 +1,37 @@
-use std::fmt::Debug;
+use std::{
+    collections::{HashMap, VecDeque},
+    f32::consts::TAU,
+    fmt::Debug,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 use tiny_skia::{
-    Color, FillRule, Mask, Paint, Path, PathBuilder, Pixmap, Rect, Stroke, Transform,
+    Color, FillRule, FilterQuality, LineCap, Mask, Paint, Path, PathBuilder, Pattern,
+    Pixmap, PixmapPaint, Rect, SpreadMode, Stroke, Transform,
 };
 
-use crate::config::{self, FillDir};
+use ab_glyph::FontArc;
+use serde::Deserialize;
+
+use crate::{
+    config::{self, Align, FillDir, Id, Units},
+    controllerdb::Database,
+};
 
 #[derive(Debug, Default)]
 pub struct Gamepad<'b> {
     pub backend: Option<Box<dyn Backend + 'b>>,
     pub inputs: Inputs,
     pub input_state: InputState,
+    /// what each history element has seen, oldest first
+    pub history: Vec<VecDeque<(Instant, Snapshot)>>,
+    /// recent positions of each stick that has a trail
+    pub trails: Vec<VecDeque<(Instant, (f32, f32))>>,
+    /// the value each readout is showing and when it started showing it
+    pub readouts: Vec<Option<(Instant, (f32, f32))>>,
+    /// the last time that `poll` ran
+    pub polled: Option<Instant>,
 }
 
 impl<'b> Gamepad<'b> {
@@ -18,12 +39,18 @@ impl<'b> Gamepad<'b> {
     fn new(config: &config::Gamepad) -> Self {
         let inputs: Inputs = config.into();
         let input_state = (&inputs).into();
-        Self { backend: None, inputs, input_state }
+        let history = vec![VecDeque::new(); inputs.history.len()];
+        let trails = vec![VecDeque::new(); inputs.sticks.len()];
+        let readouts = vec![None; inputs.readouts.len()];
+        Self { backend: None, inputs, input_state, history, trails, readouts, polled: None }
     }
 
     pub fn reload(&mut self, config: &config::Gamepad) {
         self.inputs = config.into();
         self.input_state = (&self.inputs).into();
+        self.history = vec![VecDeque::new(); self.inputs.history.len()];
+        self.trails = vec![VecDeque::new(); self.inputs.sticks.len()];
+        self.readouts = vec![None; self.inputs.readouts.len()];
         if let Some(b) = &mut self.backend {
             b.reload(&self.inputs)
         }
@@ -37,12 +64,52 @@ impl<'b> Gamepad<'b> {
     ) -> Result<(), B::Err> {
         self.inputs = config.into();
         self.input_state = (&self.inputs).into();
+        self.history = vec![VecDeque::new(); self.inputs.history.len()];
+        self.trails = vec![VecDeque::new(); self.inputs.sticks.len()];
+        self.readouts = vec![None; self.inputs.readouts.len()];
         self.backend = Some(Box::new(B::init(state, &self.inputs)?));
         Ok(())
     }
 
     pub fn poll(&mut self) -> bool {
-        self.backend.as_mut().map(|b| b.poll(&mut self.input_state)).unwrap_or_default()
+        let Some(backend) = &mut self.backend else { return false };
+        let changed = backend.poll(&mut self.input_state);
+        let connected = backend.connected();
+        let reconnected = self.input_state.connected != connected;
+        self.input_state.connected = connected;
+
+        let now = Instant::now();
+        let then = self.polled.replace(now).unwrap_or(now);
+        let mut ticked = false;
+        for (history, log) in self.inputs.history.iter().zip(&mut self.history) {
+            history.record(log, history.snapshot(&self.inputs, &self.input_state), now);
+            ticked |= history.ticked(log, then, now);
+        }
+        let mut fading = false;
+        let sticks = self.inputs.sticks.iter().zip(&self.input_state.sticks);
+        for ((stick, &pos), log) in sticks.zip(&mut self.trails) {
+            let Some(trail) = &stick.trail else { continue };
+            if log.back().is_none_or(|&(_, last)| last != pos) {
+                log.push_back((now, pos));
+            }
+            let window = trail.interval * trail.length as u32;
+            while log.get(1).is_some_and(|(t, _)| now.duration_since(*t) > window) {
+                log.pop_front();
+            }
+            // a single position means the stick has been still for the whole trail
+            fading |= log.len() > 1;
+        }
+        let mut updated = false;
+        for (readout, shown) in self.inputs.readouts.iter().zip(&mut self.readouts) {
+            let value = readout.value(&self.input_state);
+            if shown.is_none_or(|(since, old)| {
+                old != value && now.duration_since(since) >= readout.interval
+            }) {
+                *shown = Some((now, value));
+                updated = true;
+            }
+        }
+        changed || reconnected || fading || updated || ticked
     }
 }
 
@@ -51,13 +118,43 @@ pub struct Inputs {
     pub buttons: Vec<Button>,
     pub sticks: Vec<Stick>,
     pub axes: Vec<Axis>,
+    pub dpads: Vec<Dpad>,
+    pub history: Vec<History>,
+    pub readouts: Vec<Readout>,
+    pub keys: HashMap<String, Id>,
+    pub mappings: Arc<Database>,
+    pub disconnected: Option<Disconnected>,
+    /// drawn behind everything with its top left corner at the given position
+    pub background: Option<(Arc<Pixmap>, (f32, f32))>,
+    /// where the top left corner of the image is in the layout's coordinates
+    pub origin: (f32, f32),
 }
 
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone, Default, Deserialize)]
 pub struct InputState {
+    #[serde(default)]
     pub buttons: Vec<bool>,
+    #[serde(default)]
     pub sticks: Vec<(f32, f32)>,
+    #[serde(default)]
     pub axes: Vec<f32>,
+    #[serde(default)]
+    pub dpads: Vec<Directions>,
+    #[serde(default = "connected")]
+    pub connected: bool,
+}
+
+const fn connected() -> bool {
+    true
+}
+
+/// An id in the layout that the device doesn't have, so that element never changes
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MissingId {
+    /// the array that the element is in, like `buttons`
+    pub key: &'static str,
+    pub index: usize,
+    pub id: Id,
 }
 
 pub trait Backend: Debug {
@@ -75,12 +172,34 @@ pub trait Backend: Debug {
     fn poll(&mut self, state: &mut InputState) -> bool;
 
     fn reload(&mut self, inputs: &Inputs);
+
+    fn connected(&self) -> bool;
+
+    /// The ids that the device didn't have as of the last (re)load
+    fn missing_ids(&self) -> &[MissingId] {
+        &[]
+    }
+
+    /// Only does anything for backends that play back recorded inputs
+    #[allow(dead_code)]
+    fn toggle_pause(&mut self) {}
+
+    /// Skips forwards or backwards by some number of frames in recorded inputs
+    #[allow(dead_code)]
+    fn seek(&mut self, _frames: i64) {}
 }
 
 impl Inputs {
     pub fn minimize(&mut self) {
         let bounds = self.bounds();
         let t = Transform::from_translate(-bounds.left(), -bounds.top());
+        self.origin = (self.origin.0 + bounds.left(), self.origin.1 + bounds.top());
+        let labels = (self.buttons.iter_mut().filter_map(|b| b.label.as_mut()))
+            .chain(self.axes.iter_mut().filter_map(|a| a.label.as_mut()))
+            .chain(self.sticks.iter_mut().filter_map(|s| s.label.as_mut()));
+        for l in labels {
+            l.path = l.path.clone().transform(t).unwrap();
+        }
         for b in &mut self.buttons {
             b.path = b.path.clone().transform(t).unwrap();
         }
@@ -93,6 +212,31 @@ impl Inputs {
         for a in &mut self.axes {
             a.path = a.path.clone().transform(t).unwrap();
         }
+        for d in &mut self.dpads {
+            for arm in &mut d.arms {
+                *arm = arm.clone().transform(t).unwrap();
+            }
+            d.center = d.center.clone().transform(t).unwrap();
+            if let Some((path, _, _)) = &mut d.outline {
+                *path = path.clone().transform(t).unwrap();
+            }
+        }
+        for h in &mut self.history {
+            h.pos = (h.pos.0 - bounds.left(), h.pos.1 - bounds.top());
+        }
+        for r in &mut self.readouts {
+            r.pos = (r.pos.0 - bounds.left(), r.pos.1 - bounds.top());
+            r.bounds = r.bounds.transform(t).unwrap();
+        }
+        if let Some((_, pos)) = &mut self.background {
+            *pos = (pos.0 - bounds.left(), pos.1 - bounds.top());
+        }
+        if let Some(Disconnected { badge: Some((background, _, cross, _)), .. }) =
+            &mut self.disconnected
+        {
+            *background = background.clone().transform(t).unwrap();
+            *cross = cross.clone().transform(t).unwrap();
+        }
     }
 
     pub fn bounds(&self) -> Rect {
@@ -101,6 +245,13 @@ impl Inputs {
             .map(Button::bounds)
             .chain(self.sticks.iter().map(Stick::bounds))
             .chain(self.axes.iter().map(Axis::bounds))
+            .chain(self.dpads.iter().map(Dpad::bounds))
+            .chain(self.history.iter().map(History::bounds))
+            .chain(self.readouts.iter().map(|r| r.bounds))
+            .chain(self.disconnected.iter().filter_map(Disconnected::bounds))
+            .chain(self.background.iter().filter_map(|(image, (x, y))| {
+                Rect::from_xywh(*x, *y, image.width() as f32, image.height() as f32)
+            }))
             .reduce(combine)
             .unwrap_or_else(|| Rect::from_ltrb(0.0, 0.0, 100.0, 100.0).unwrap())
     }
@@ -108,10 +259,13 @@ impl Inputs {
 
 #[derive(Clone, Debug)]
 pub struct Button {
-    pub id: u8,
+    pub id: Id,
     pub path: Path,
     pub fill: ColorPair,
     pub outline: Option<(ColorPair, f32)>,
+    pub image: Option<Arc<Pixmap>>,
+    pub image_active: Option<Arc<Pixmap>>,
+    pub label: Option<Label>,
 }
 
 #[derive(Clone, Debug)]
@@ -121,6 +275,7 @@ pub struct Axis {
     pub direction: FillDir,
     pub fill: ColorPair,
     pub outline: Option<(Color, f32)>,
+    pub label: Option<Label>,
 }
 
 #[derive(Clone, Debug)]
@@ -130,28 +285,561 @@ pub struct Stick {
     pub deadzone: f32,
     pub path: Path,
     pub displacement: f32,
+    pub gate_shape: GateShape,
     pub fill: ColorPair,
     pub outline: Option<(ColorPair, f32)>,
     pub gate: Option<(Path, ColorPair, f32)>,
+    pub trail: Option<Trail>,
+    pub tilt: bool,
+    pub shaft: Option<(Color, f32)>,
+    pub label: Option<Label>,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum ReadoutSource {
+    Stick(usize),
+    Axis(usize),
+}
+
+#[derive(Clone, Debug)]
+pub struct Readout {
+    pub pos: (f32, f32),
+    pub source: ReadoutSource,
+    pub units: Units,
+    pub range: (f32, f32),
+    pub format: String,
+    /// minimum time between changes
+    pub interval: Duration,
+    pub font: FontArc,
+    pub size: f32,
+    pub align: Align,
+    pub color: Color,
+    /// how much space the text can take up
+    pub bounds: Rect,
+}
+
+impl Readout {
+    /// The current value with up being positive, axes only use the first half
+    pub fn value(&self, state: &InputState) -> (f32, f32) {
+        match self.source {
+            ReadoutSource::Stick(i) => {
+                state.sticks.get(i).map_or((0.0, 0.0), |&(x, y)| (x, -y))
+            }
+            ReadoutSource::Axis(i) => (state.axes.get(i).copied().unwrap_or_default(), 0.0),
+        }
+    }
+
+    fn convert(&self, v: f32) -> f32 {
+        let stick = matches!(self.source, ReadoutSource::Stick(_));
+        match self.units {
+            Units::Normalized => v,
+            Units::Scaled => {
+                let (min, max) = self.range;
+                let v = if stick { (v + 1.0) / 2.0 } else { v };
+                min + v * (max - min)
+            }
+            Units::Melee if stick => (v * 80.0).round().clamp(-80.0, 80.0),
+            Units::Melee => (v * 140.0).round().clamp(0.0, 140.0),
+        }
+    }
+
+    /// Fills in `{name}` or `{name:+.N}` placeholders, `{{` is a literal brace
+    pub fn text(&self, (x, y): (f32, f32)) -> String {
+        let default_precision = match self.units {
+            Units::Normalized => 4,
+            Units::Scaled | Units::Melee => 0,
+        };
+        let mut out = String::new();
+        let mut rest = self.format.as_str();
+        while let Some(start) = rest.find(['{', '}']) {
+            out.push_str(&rest[..start]);
+            let brace = &rest[start..];
+            if brace.starts_with("{{") || brace.starts_with("}}") {
+                out.push_str(&brace[..1]);
+                rest = &brace[2..];
+                continue;
+            }
+            let Some(end) = brace.find('}').filter(|_| brace.starts_with('{')) else {
+                out.push_str(&brace[..1]);
+                rest = &brace[1..];
+                continue;
+            };
+            let (name, spec) =
+                brace[1..end].split_once(':').unwrap_or((&brace[1..end], ""));
+            let value = match name {
+                "x" | "value" => Some(x),
+                "y" => Some(y),
+                _ => None,
+            };
+            let sign = spec.starts_with('+');
+            let precision = spec
+                .trim_start_matches('+')
+                .strip_prefix('.')
+                .and_then(|p| p.parse().ok())
+                .unwrap_or(default_precision);
+            match value {
+                Some(v) => {
+                    let v = self.convert(v);
+                    let mut s = match sign {
+                        true => format!("{v:+.precision$}"),
+                        false => format!("{v:.precision$}"),
+                    };
+                    // tiny negative values shouldn't show up as -0.000
+                    if s.starts_with('-') && s[1..].chars().all(|c| c == '0' || c == '.') {
+                        s.replace_range(..1, if sign { "+" } else { "" });
+                    }
+                    out.push_str(&s);
+                }
+                None => out.push_str(&brace[..=end]),
+            }
+            rest = &brace[end + 1..];
+        }
+        out.push_str(rest);
+        out
+    }
+
+    pub fn path(&self, value: (f32, f32)) -> Option<Path> {
+        config::text_path(&self.font, &self.text(value), self.size, self.align, self.pos)
+    }
+}
+
+/// Glyph outlines that get filled like any other shape
+#[derive(Clone, Debug)]
+pub struct Label {
+    pub path: Path,
+    pub color: ColorPair,
+}
+
+#[derive(Clone, Debug)]
+pub struct Trail {
+    pub length: usize,
+    /// time between samples
+    pub interval: Duration,
+    pub color: Color,
+    pub width: f32,
+    pub dots: bool,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum GateShape {
+    Circle,
+    /// regular polygon with its first corner `rotation` radians clockwise from the right
+    Polygon {
+        sides: u8,
+        rotation: f32,
+    },
+}
+
+impl GateShape {
+    pub fn path(&self, x: f32, y: f32, radius: f32) -> Path {
+        match *self {
+            GateShape::Circle => PathBuilder::from_circle(x, y, radius).unwrap(),
+            GateShape::Polygon { sides, rotation } => {
+                let mut path = PathBuilder::new();
+                for i in 0..sides {
+                    let theta = rotation + TAU * i as f32 / sides as f32;
+                    let (px, py) = (x + radius * theta.cos(), y + radius * theta.sin());
+                    if i == 0 { path.move_to(px, py) } else { path.line_to(px, py) }
+                }
+                path.close();
+                path.finish().unwrap()
+            }
+        }
+    }
+
+    /// Pulls a normalized stick position back onto the edge of the gate if it's outside
+    pub fn clamp(&self, x: f32, y: f32) -> (f32, f32) {
+        let len = x.hypot(y);
+        let max = match *self {
+            GateShape::Circle => 1.0,
+            GateShape::Polygon { sides, rotation } => {
+                // distance from the center to the edge of a polygon with a circumradius
+                // of 1, in the direction that the stick is pointing
+                let wedge = TAU / sides as f32;
+                let angle = (y.atan2(x) - rotation).rem_euclid(wedge);
+                (wedge / 2.0).cos() / (angle - wedge / 2.0).cos()
+            }
+        };
+        if len > max { (x * max / len, y * max / len) } else { (x, y) }
+    }
 }
 
-// TODO
-#[allow(unused)]
 #[derive(Clone, Debug)]
 pub struct Dpad {
-    pub x: RawAxis,
-    pub y: RawAxis,
-    pub up: Path,
-    pub down: Path,
-    pub left: Path,
-    pub right: Path,
-    pub fill: ColorPair,
-    pub outline: Option<(Path, Color, f32)>,
+    pub source: DpadSource,
+    /// up, down, left, right
+    pub arms: [Path; 4],
+    pub center: Path,
+    pub fill: [ColorPair; 4],
+    pub outline: Option<(Path, ColorPair, f32)>,
+}
+
+#[derive(Clone, Debug)]
+pub enum DpadSource {
+    Hat(RawAxis, RawAxis),
+    /// up, down, left, right
+    Buttons([Id; 4]),
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Direction {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct Directions {
+    pub up: bool,
+    pub down: bool,
+    pub left: bool,
+    pub right: bool,
+}
+
+impl Directions {
+    pub fn set(&mut self, dir: Direction, pressed: bool) {
+        match dir {
+            Direction::Up => self.up = pressed,
+            Direction::Down => self.down = pressed,
+            Direction::Left => self.left = pressed,
+            Direction::Right => self.right = pressed,
+        }
+    }
+
+    /// takes a hat axis that's been normalized to -1..1
+    pub fn set_x(&mut self, x: f32, invert: bool) {
+        let x = if invert { -x } else { x };
+        self.left = x < -0.5;
+        self.right = x > 0.5;
+    }
+
+    pub fn set_y(&mut self, y: f32, invert: bool) {
+        let y = if invert { -y } else { y };
+        self.up = y < -0.5;
+        self.down = y > 0.5;
+    }
+
+    pub fn any(&self) -> bool {
+        self.up || self.down || self.left || self.right
+    }
+
+    pub fn as_array(&self) -> [bool; 4] {
+        [self.up, self.down, self.left, self.right]
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct History {
+    pub mode: HistoryMode,
+    /// top left corner
+    pub pos: (f32, f32),
+    /// indices into the layout's buttons
+    pub buttons: Vec<usize>,
+    pub direction: Option<HistorySource>,
+    pub row_height: f32,
+    pub fill: Option<Color>,
+    pub color: Color,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum HistoryMode {
+    List { length: usize },
+    Timeline { width: f32, duration: Duration },
+}
+
+/// Where the direction in a history list comes from, as an index into the layout's
+/// sticks or dpads
+#[derive(Clone, Copy, Debug)]
+pub enum HistorySource {
+    Stick(usize),
+    Dpad(usize),
+}
+
+/// The parts of the input state that a history element shows
+#[derive(Clone, Debug, PartialEq)]
+pub struct Snapshot {
+    /// -1, 0, or 1 for each axis, with down being positive
+    pub direction: (i8, i8),
+    pub buttons: Vec<bool>,
+}
+
+impl History {
+    /// Sticks, dpads and buttons that don't exist read as neutral
+    pub fn snapshot(&self, inputs: &Inputs, state: &InputState) -> Snapshot {
+        let sign = |neg: bool, pos: bool| pos as i8 - neg as i8;
+        let direction = match self.direction {
+            Some(HistorySource::Stick(i)) => {
+                let (x, y) = state.sticks.get(i).copied().unwrap_or_default();
+                let stick = inputs.sticks.get(i);
+                let x = if stick.is_some_and(|s| s.x.invert) { -x } else { x };
+                let y = if stick.is_some_and(|s| s.y.invert) { -y } else { y };
+                (sign(x < -0.5, x > 0.5), sign(y < -0.5, y > 0.5))
+            }
+            Some(HistorySource::Dpad(i)) => {
+                let d = state.dpads.get(i).copied().unwrap_or_default();
+                (sign(d.left, d.right), sign(d.up, d.down))
+            }
+            None => (0, 0),
+        };
+        let pressed = |&i: &usize| state.buttons.get(i).copied().unwrap_or_default();
+        Snapshot { direction, buttons: self.buttons.iter().map(pressed).collect() }
+    }
+
+    pub fn record(
+        &self,
+        log: &mut VecDeque<(Instant, Snapshot)>,
+        snapshot: Snapshot,
+        now: Instant,
+    ) {
+        if log.back().is_none_or(|(_, last)| *last != snapshot) {
+            log.push_back((now, snapshot));
+        }
+        match self.mode {
+            HistoryMode::List { length } => {
+                while log.len() > length {
+                    log.pop_front();
+                }
+            }
+            // the oldest entry is still needed as long as the one after it is on screen
+            HistoryMode::Timeline { duration, .. } => {
+                while log.get(1).is_some_and(|(t, _)| now.duration_since(*t) > duration) {
+                    log.pop_front();
+                }
+            }
+        }
+    }
+
+    /// Whether it shows something different at `now` than it did at `then`, even
+    /// though the inputs are the same. That's the frame count of the newest row in a
+    /// list, or a timeline that has bars scrolling along it.
+    pub fn ticked(
+        &self,
+        log: &VecDeque<(Instant, Snapshot)>,
+        then: Instant,
+        now: Instant,
+    ) -> bool {
+        let Some((start, _)) = log.back() else { return false };
+        match self.mode {
+            HistoryMode::List { .. } => frames(*start, then) != frames(*start, now),
+            HistoryMode::Timeline { .. } => {
+                log.iter().any(|(_, snapshot)| snapshot.buttons.contains(&true))
+            }
+        }
+    }
+
+    /// width of the frame counter in list mode
+    fn counter_width(&self) -> f32 {
+        let (digit, gap) = digit_size(self.row_height);
+        3.0 * (digit + gap) + gap
+    }
+
+    pub fn bounds(&self) -> Rect {
+        let h = self.row_height;
+        let (width, height) = match self.mode {
+            HistoryMode::List { length } => (
+                self.counter_width()
+                    + if self.direction.is_some() { h } else { 0.0 }
+                    + self.buttons.len() as f32 * h,
+                length.max(1) as f32 * h,
+            ),
+            HistoryMode::Timeline { width, .. } => {
+                (width, self.buttons.len().max(1) as f32 * h)
+            }
+        };
+        Rect::from_xywh(self.pos.0, self.pos.1, width.max(1.0), height).unwrap()
+    }
+}
+
+impl History {
+    fn render(
+        &self,
+        img: &mut Pixmap,
+        paint: &mut Paint,
+        fill: impl Fn(Color) -> Color,
+        log: &VecDeque<(Instant, Snapshot)>,
+        buttons: &[Button],
+        now: Instant,
+    ) {
+        let (f, t) = (FillRule::default(), Transform::default());
+        let bounds = self.bounds();
+        let h = self.row_height;
+        if let Some(color) = self.fill {
+            paint.set_color(fill(color));
+            img.fill_rect(bounds, paint, t, None);
+        }
+        // when each snapshot started and ended
+        let ends = log.iter().skip(1).map(|(t, _)| *t).chain([now]);
+        let spans: Vec<_> = log
+            .iter()
+            .zip(ends)
+            .map(|((start, snapshot), end)| (*start, end, snapshot))
+            .collect();
+
+        match self.mode {
+            HistoryMode::List { .. } => {
+                let counter = self.counter_width();
+                let (_, gap) = digit_size(h);
+                for (row, (start, end, snapshot)) in spans.iter().rev().enumerate() {
+                    let top = bounds.top() + row as f32 * h;
+                    let mid = top + h / 2.0;
+                    paint.set_color(fill(self.color));
+                    let right = bounds.left() + counter - gap;
+                    if let Some(path) =
+                        digits(frames(*start, *end), right, top + h * 0.2, h * 0.6)
+                    {
+                        img.fill_path(&path, paint, f, t, None);
+                    }
+
+                    let mut x = bounds.left() + counter;
+                    if self.direction.is_some() {
+                        let arrow = arrow(x + h / 2.0, mid, h * 0.8, snapshot.direction);
+                        img.fill_path(&arrow, paint, f, t, None);
+                        x += h;
+                    }
+                    for (&i, _) in
+                        self.buttons.iter().zip(&snapshot.buttons).filter(|b| *b.1)
+                    {
+                        paint.set_color(fill(buttons[i].fill.active));
+                        let dot =
+                            PathBuilder::from_circle(x + h / 2.0, mid, h * 0.35).unwrap();
+                        img.fill_path(&dot, paint, f, t, None);
+                        x += h;
+                    }
+                }
+            }
+            HistoryMode::Timeline { width, duration } => {
+                let x = |time: Instant| {
+                    let age =
+                        now.duration_since(time).as_secs_f32() / duration.as_secs_f32();
+                    (bounds.right() - width * age).max(bounds.left())
+                };
+                for (row, &i) in self.buttons.iter().enumerate() {
+                    paint.set_color(fill(buttons[i].fill.active));
+                    let top = bounds.top() + row as f32 * h;
+                    for (start, end, snapshot) in &spans {
+                        if snapshot.buttons[row]
+                            && let Some(rect) = Rect::from_ltrb(
+                                x(*start),
+                                top + h * 0.15,
+                                x(*end),
+                                top + h * 0.85,
+                            )
+                        {
+                            img.fill_rect(rect, paint, t, None);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// How many frames (at 60fps) a row in a history list has lasted, as many as fit in
+/// its counter
+fn frames(start: Instant, end: Instant) -> u32 {
+    ((end.saturating_duration_since(start).as_secs_f32() * 60.0) as u32).min(999)
+}
+
+/// width of a digit and the space after it, for a row of the given height
+fn digit_size(row_height: f32) -> (f32, f32) {
+    let height = row_height * 0.6;
+    (height / 2.0, height / 4.0)
+}
+
+/// Seven segment style digits for `n`, right aligned to `right`
+fn digits(n: u32, right: f32, top: f32, height: f32) -> Option<Path> {
+    // segments from a to g: top, top right, bottom right, bottom, bottom left, top
+    // left, middle
+    const SEGMENTS: [u8; 10] = [0x3F, 0x06, 0x5B, 0x4F, 0x66, 0x6D, 0x7D, 0x07, 0x7F, 0x6F];
+    let (w, gap) = digit_size(height / 0.6);
+    let (s, half) = (height / 8.0, height / 2.0);
+    let mut path = PathBuilder::new();
+    let mut rect = |x, y, w, h| path.push_rect(Rect::from_xywh(x, y, w, h).unwrap());
+    let mut n = n;
+    let mut x = right - w;
+    loop {
+        let (l, t) = (x, top);
+        let segments = SEGMENTS[(n % 10) as usize];
+        for (i, (x, y, w, h)) in [
+            (l, t, w, s),
+            (l + w - s, t, s, half),
+            (l + w - s, t + half, s, half),
+            (l, t + height - s, w, s),
+            (l, t + half, s, half),
+            (l, t, s, half),
+            (l, t + half - s / 2.0, w, s),
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            if segments & 1 << i != 0 {
+                rect(x, y, w, h);
+            }
+        }
+        n /= 10;
+        if n == 0 {
+            break;
+        }
+        x -= w + gap;
+    }
+    path.finish()
+}
+
+/// An arrow centered on `(x, y)` pointing in `direction`, or a dot for neutral
+fn arrow(x: f32, y: f32, size: f32, direction: (i8, i8)) -> Path {
+    let (dx, dy) = direction;
+    if direction == (0, 0) {
+        return PathBuilder::from_circle(x, y, size / 4.0).unwrap();
+    }
+    let r = size / 2.0;
+    let mut path = PathBuilder::new();
+    path.move_to(-r, -r / 4.0);
+    for (px, py) in [
+        (0.0, -r / 4.0),
+        (0.0, -r * 0.7),
+        (r, 0.0),
+        (0.0, r * 0.7),
+        (0.0, r / 4.0),
+        (-r, r / 4.0),
+    ] {
+        path.line_to(px, py);
+    }
+    path.close();
+    let angle = (dy as f32).atan2(dx as f32).to_degrees();
+    path.finish()
+        .unwrap()
+        .transform(Transform::from_rotate(angle).post_translate(x, y))
+        .unwrap()
+}
+
+/// Stretches an image over the bounds of a path so filling the path clips it
+fn image_paint<'a>(image: &'a Pixmap, path: &Path) -> Paint<'a> {
+    let r = tight_bounds(path);
+    let t = Transform::from_scale(
+        r.width() / image.width() as f32,
+        r.height() / image.height() as f32,
+    )
+    .post_translate(r.left(), r.top());
+    let shader =
+        Pattern::new(image.as_ref(), SpreadMode::Pad, FilterQuality::Bicubic, 1.0, t);
+    Paint { shader, anti_alias: true, ..Default::default() }
+}
+
+/// How to draw the layout when the backend isn't connected
+#[derive(Clone, Debug)]
+pub struct Disconnected {
+    pub opacity: f32,
+    pub fill: Option<Color>,
+    pub outline: Option<Color>,
+    /// a circle and the X drawn on top of it
+    pub badge: Option<(Path, Color, Path, Color)>,
 }
 
 #[derive(Clone, Debug, Default)]
 pub struct RawAxis {
-    pub id: u8,
+    pub id: Id,
     pub invert: bool,
 }
 
@@ -161,6 +849,18 @@ impl From<&config::Gamepad> for Inputs {
             buttons: config.buttons.iter().map(|b| b.load(config)).collect(),
             axes: config.axes.iter().map(|b| b.load(config)).collect(),
             sticks: config.sticks.iter().map(|b| b.load(config)).collect(),
+            dpads: config.dpads.iter().map(|d| d.load(config)).collect(),
+            history: config.history.iter().map(|h| h.load(config)).collect(),
+            readouts: config.readouts.iter().filter_map(|r| r.load(config)).collect(),
+            keys: config.keys.clone(),
+            mappings: (config.mappings.as_ref().and_then(|m| m.db.clone()))
+                .unwrap_or_else(Database::bundled),
+            disconnected: config.disconnected.as_ref().map(|d| d.load()),
+            background: config
+                .background
+                .as_ref()
+                .and_then(|b| b.image.pixmap.clone().map(|image| (image, b.pos))),
+            origin: (0.0, 0.0),
         };
         temp.minimize();
         temp
@@ -175,23 +875,57 @@ impl Gamepad<'_> {
         let t = Transform::default();
         img.fill(Color::TRANSPARENT);
 
+        let offline = if self.input_state.connected {
+            None
+        } else {
+            self.inputs.disconnected.as_ref()
+        };
+        let fill = |c| offline.and_then(|d| d.fill).unwrap_or(c);
+        let outline = |c| offline.and_then(|d| d.outline).unwrap_or(c);
+
+        if let Some((image, (x, y))) = &self.inputs.background {
+            let t = Transform::from_translate(*x, *y);
+            img.draw_pixmap(
+                0,
+                0,
+                image.as_ref().as_ref(),
+                &PixmapPaint::default(),
+                t,
+                None,
+            );
+        }
+
         for (button, &pressed) in self.inputs.buttons.iter().zip(&self.input_state.buttons)
         {
-            paint.set_color(button.fill.get(pressed));
-            img.fill_path(&button.path, &paint, f, t, None);
+            let image = match pressed {
+                true => button.image_active.as_ref().or(button.image.as_ref()),
+                false => button.image.as_ref(),
+            };
+            if let Some(image) = image {
+                img.fill_path(&button.path, &image_paint(image, &button.path), f, t, None);
+            }
+            // the active color tints `image` if there's no separate active image
+            if image.is_none() || (pressed && button.image_active.is_none()) {
+                paint.set_color(fill(button.fill.get(pressed)));
+                img.fill_path(&button.path, &paint, f, t, None);
+            }
 
             if let Some((colors, weight)) = &button.outline {
-                paint.set_color(colors.get(pressed));
+                paint.set_color(outline(colors.get(pressed)));
                 stroke.width = *weight;
                 img.stroke_path(&button.path, &paint, &stroke, t, None);
             }
+
+            if let Some(label) = &button.label {
+                label.render(img, &mut paint, pressed, t);
+            }
         }
 
         let mut mask = Mask::new(img.width(), img.height()).unwrap();
         for (axis, &percent) in self.inputs.axes.iter().zip(&self.input_state.axes) {
             // background
-            let rect = axis.path.bounds();
-            paint.set_color(axis.fill.inactive);
+            let rect = tight_bounds(&axis.path);
+            paint.set_color(fill(axis.fill.inactive));
             img.fill_path(&axis.path, &paint, f, t, None);
 
             let percent = if axis.axis.invert { 1.0 - percent } else { percent };
@@ -207,15 +941,15 @@ impl Gamepad<'_> {
                 BottomToTop => top += rect.height() * (1.0 - percent),
                 RightToLeft => left += rect.width() * (1.0 - percent),
             };
-            if let Some(rect) = Rect::from_ltrb(left, top, right, bottom)
-                && rect.width() > 0.05
-                && rect.height() > 0.05
-            {
+            // the label counts as active whenever some of the fill shows
+            let active = Rect::from_ltrb(left, top, right, bottom)
+                .filter(|rect| rect.width() > 0.05 && rect.height() > 0.05);
+            if let Some(rect) = active {
                 mask.clear();
                 mask.fill_path(&axis.path, tiny_skia::FillRule::Winding, true, t);
 
                 let active_path = PathBuilder::from_rect(rect);
-                paint.set_color(axis.fill.active);
+                paint.set_color(fill(axis.fill.active));
                 // img.fill_path(&active_path, &paint, f, t, None);
                 img.fill_path(&active_path, &paint, f, t, Some(&mask));
             }
@@ -223,40 +957,117 @@ impl Gamepad<'_> {
             // border
             if let Some((color, weight)) = axis.outline {
                 stroke.width = weight;
-                paint.set_color(color);
+                paint.set_color(outline(color));
                 img.stroke_path(&axis.path, &paint, &stroke, t, None);
             }
+
+            if let Some(label) = &axis.label {
+                label.render(img, &mut paint, active.is_some(), t);
+            }
         }
 
-        for (stick, &(x, y)) in self.inputs.sticks.iter().zip(&self.input_state.sticks) {
+        let now = Instant::now();
+        let sticks = self.inputs.sticks.iter().zip(&self.input_state.sticks);
+        for ((stick, &(x, y)), log) in sticks.zip(&self.trails) {
             let deadzone = stick.deadzone;
             let is_active =
                 !(-deadzone < x && x < deadzone && -deadzone < y && y < deadzone);
-            let x = if stick.x.invert { -x } else { x };
-            let y = if stick.x.invert { -y } else { y };
-            let cx = stick.displacement * x * (1.0 - y * y / 2.0).sqrt();
-            let cy = stick.displacement * y * (1.0 - x * x / 2.0).sqrt();
+            let (cx, cy) = stick.offset(x, y);
 
             if let Some((path, color, weight)) = &stick.gate {
-                paint.set_color(color.get(is_active));
+                paint.set_color(outline(color.get(is_active)));
                 stroke.width = *weight;
                 img.stroke_path(path, &paint, &stroke, t, None);
             }
 
-            let trans = Transform::from_translate(cx, cy);
-            paint.set_color(stick.fill.get(is_active));
+            if let Some(trail) = &stick.trail {
+                trail.render(img, &mut paint, fill, log, stick, now);
+            }
+
+            if let Some((color, weight)) = stick.shaft {
+                let (x, y) = stick.center();
+                let mut shaft = PathBuilder::new();
+                shaft.move_to(x, y);
+                shaft.line_to(x + cx, y + cy);
+                if let Some(shaft) = shaft.finish() {
+                    paint.set_color(outline(color));
+                    let stroke = Stroke {
+                        width: weight,
+                        line_cap: LineCap::Round,
+                        ..Default::default()
+                    };
+                    img.stroke_path(&shaft, &paint, &stroke, t, None);
+                }
+            }
+
+            let trans = stick.knob_transform(cx, cy);
+            paint.set_color(fill(stick.fill.get(is_active)));
             img.fill_path(&stick.path, &paint, f, trans, None);
 
             if let Some((colors, weight)) = &stick.outline {
-                paint.set_color(colors.get(is_active));
+                paint.set_color(outline(colors.get(is_active)));
                 stroke.width = *weight;
                 img.stroke_path(&stick.path, &paint, &stroke, trans, None);
             }
+
+            if let Some(label) = &stick.label {
+                label.render(img, &mut paint, is_active, Transform::from_translate(cx, cy));
+            }
+        }
+
+        for (dpad, dirs) in self.inputs.dpads.iter().zip(&self.input_state.dpads) {
+            let pressed = dirs.as_array();
+            paint.set_color(fill(dpad.fill[0].inactive));
+            img.fill_path(&dpad.center, &paint, f, t, None);
+            for ((arm, color), pressed) in dpad.arms.iter().zip(&dpad.fill).zip(pressed) {
+                paint.set_color(fill(color.get(pressed)));
+                img.fill_path(arm, &paint, f, t, None);
+            }
+
+            if let Some((path, colors, weight)) = &dpad.outline {
+                paint.set_color(outline(colors.get(dirs.any())));
+                stroke.width = *weight;
+                img.stroke_path(path, &paint, &stroke, t, None);
+            }
+        }
+
+        for (history, log) in self.inputs.history.iter().zip(&self.history) {
+            history.render(img, &mut paint, fill, log, &self.inputs.buttons, now);
+        }
+
+        for (readout, shown) in self.inputs.readouts.iter().zip(&self.readouts) {
+            let value = shown.map_or_else(|| readout.value(&self.input_state), |(_, v)| v);
+            if let Some(path) = readout.path(value) {
+                paint.set_color(fill(readout.color));
+                img.fill_path(&path, &paint, f, t, None);
+            }
+        }
+
+        if let Some(offline) = offline {
+            if offline.opacity < 1.0 {
+                // everything is premultiplied, so dimming is the same for every channel
+                for byte in img.data_mut() {
+                    *byte = (*byte as f32 * offline.opacity) as u8;
+                }
+            }
+            if let Some((background, color, cross, cross_color)) = &offline.badge {
+                paint.set_color(*color);
+                img.fill_path(background, &paint, f, t, None);
+                paint.set_color(*cross_color);
+                stroke.width = background.bounds().width() / 8.0;
+                img.stroke_path(cross, &paint, &stroke, t, None);
+            }
         }
     }
 }
 
-fn combine(a: Rect, b: Rect) -> Rect {
+/// `Path::bounds` includes the control points of curves, which can be way outside of
+/// the actual shape
+pub fn tight_bounds(path: &Path) -> Rect {
+    path.compute_tight_bounds().unwrap_or_else(|| path.bounds())
+}
+
+pub fn combine(a: Rect, b: Rect) -> Rect {
     Rect::from_ltrb(
         a.left().min(b.left()),
         a.top().min(b.top()),
@@ -288,43 +1099,162 @@ impl ColorPair {
 
 impl Button {
     pub fn bounds(&self) -> Rect {
-        if let Some((_, width)) = self.outline {
-            expand(self.path.bounds(), width)
+        let bounds = if let Some((_, width)) = self.outline {
+            expand(tight_bounds(&self.path), width)
         } else {
-            self.path.bounds()
-        }
+            tight_bounds(&self.path)
+        };
+        self.label.iter().map(Label::bounds).fold(bounds, combine)
     }
 }
 
 impl Stick {
     pub fn bounds(&self) -> Rect {
-        let mut bounds = expand(self.path.bounds(), self.displacement);
+        let mut bounds = expand(tight_bounds(&self.path), self.displacement);
         if let Some((_, width)) = self.outline {
             bounds = expand(bounds, width)
         }
         if let Some((path, _, width)) = &self.gate {
-            bounds = combine(bounds, expand(path.bounds(), *width))
+            bounds = combine(bounds, expand(tight_bounds(path), *width))
+        }
+        if let Some(label) = &self.label {
+            bounds = combine(bounds, expand(label.bounds(), self.displacement))
         }
         bounds
     }
 }
 
+impl Stick {
+    /// How far to move the stick from its center for a raw position
+    pub fn offset(&self, x: f32, y: f32) -> (f32, f32) {
+        let x = if self.x.invert { -x } else { x };
+        let y = if self.y.invert { -y } else { y };
+        let (x, y) = self.gate_shape.clamp(x, y);
+        (self.displacement * x, self.displacement * y)
+    }
+
+    pub fn center(&self) -> (f32, f32) {
+        let b = tight_bounds(&self.path);
+        (b.x() + b.width() / 2.0, b.y() + b.height() / 2.0)
+    }
+
+    /// Moves the knob by an offset, and squashes it along that direction if it's tilted
+    pub fn knob_transform(&self, dx: f32, dy: f32) -> Transform {
+        let moved = Transform::from_translate(dx, dy);
+        // a stick that can't move has nothing to tilt towards
+        if !self.tilt || self.displacement <= 0.0 {
+            return moved;
+        }
+        let distance = dx.hypot(dy) / self.displacement;
+        if distance == 0.0 {
+            return moved;
+        }
+        // a fully pushed stick looks like it's tilted about 45 degrees
+        let squash = 1.0 - 0.3 * distance.min(1.0);
+        let angle = dy.atan2(dx).to_degrees();
+        let (x, y) = self.center();
+        Transform::from_translate(-x, -y)
+            .post_rotate(-angle)
+            .post_scale(squash, 1.0)
+            .post_rotate(angle)
+            .post_translate(x + dx, y + dy)
+    }
+}
+
+impl Trail {
+    fn render(
+        &self,
+        img: &mut Pixmap,
+        paint: &mut Paint,
+        fill: impl Fn(Color) -> Color,
+        log: &VecDeque<(Instant, (f32, f32))>,
+        stick: &Stick,
+        now: Instant,
+    ) {
+        let (cx, cy) = stick.center();
+        // resample at a fixed rate, newest first, so that the trail looks the same no
+        // matter how often the backend or the renderer run
+        let mut entries = log.iter().rev().peekable();
+        let mut points = Vec::with_capacity(self.length + 1);
+        for i in 0..=self.length {
+            let Some(time) = now.checked_sub(self.interval * i as u32) else { break };
+            while entries.next_if(|(t, _)| *t > time).is_some() {}
+            let Some(&&(_, (x, y))) = entries.peek().or(log.front().as_ref()) else {
+                break;
+            };
+            let (dx, dy) = stick.offset(x, y);
+            points.push((cx + dx, cy + dy));
+        }
+
+        let stroke =
+            Stroke { width: self.width, line_cap: LineCap::Round, ..Default::default() };
+        let color = fill(self.color);
+        for (i, pair) in points.windows(2).enumerate() {
+            let mut faded = color;
+            faded.apply_opacity(1.0 - i as f32 / self.length as f32);
+            paint.set_color(faded);
+            let [(x1, y1), (x2, y2)] = [pair[0], pair[1]];
+            if self.dots {
+                let dot = PathBuilder::from_circle(x2, y2, self.width / 2.0).unwrap();
+                img.fill_path(&dot, paint, FillRule::default(), Transform::default(), None);
+            } else if (x1, y1) != (x2, y2) {
+                let mut line = PathBuilder::new();
+                line.move_to(x1, y1);
+                line.line_to(x2, y2);
+                let line = line.finish().unwrap();
+                img.stroke_path(&line, paint, &stroke, Transform::default(), None);
+            }
+        }
+    }
+}
+
 impl Axis {
     pub fn bounds(&self) -> Rect {
-        if let Some((_, width)) = &self.outline {
-            expand(self.path.bounds(), *width)
+        let bounds = if let Some((_, width)) = &self.outline {
+            expand(tight_bounds(&self.path), *width)
         } else {
-            self.path.bounds()
+            tight_bounds(&self.path)
+        };
+        self.label.iter().map(Label::bounds).fold(bounds, combine)
+    }
+}
+
+impl Label {
+    pub fn bounds(&self) -> Rect {
+        tight_bounds(&self.path)
+    }
+
+    fn render(&self, img: &mut Pixmap, paint: &mut Paint, active: bool, t: Transform) {
+        paint.set_color(self.color.get(active));
+        img.fill_path(&self.path, paint, FillRule::Winding, t, None);
+    }
+}
+
+impl Dpad {
+    pub fn bounds(&self) -> Rect {
+        let bounds = self.arms.iter().map(Path::bounds).fold(self.center.bounds(), combine);
+        if let Some((_, _, width)) = &self.outline {
+            expand(bounds, *width)
+        } else {
+            bounds
         }
     }
 }
 
+impl Disconnected {
+    pub fn bounds(&self) -> Option<Rect> {
+        self.badge.as_ref().map(|(path, ..)| path.bounds())
+    }
+}
+
 impl From<&Inputs> for InputState {
     fn from(inputs: &Inputs) -> Self {
         Self {
             buttons: vec![false; inputs.buttons.len()],
             axes: vec![0.0; inputs.axes.len()],
             sticks: vec![Default::default(); inputs.sticks.len()],
+            dpads: vec![Default::default(); inputs.dpads.len()],
+            connected: false,
         }
     }
 }
@@ -1,21 +1,336 @@
 use std::{
     collections::HashMap,
-    io::{BufRead, BufReader},
-    sync::mpsc::{self, Receiver, TryRecvError},
+    fmt,
+    io::{self, BufRead, BufReader, ErrorKind, Read},
+    sync::mpsc::{self, Receiver, Sender, TryRecvError},
     thread,
-    time::Duration,
+    time::{Duration, Instant},
 };
 
 use color_eyre::{eyre::Context, Report};
-use log::{error, trace};
-use serialport::{FlowControl, SerialPortType};
+use log::{debug, error, info, trace, warn};
+use serialport::{FlowControl, SerialPort, SerialPortType};
 
-use crate::gamepad::{Backend, InputState, Inputs};
+use crate::{
+    gamepad::{Backend, InputState, Inputs, MissingId},
+    melee::{Controller, Mapping},
+};
 
+/// Reads the state of a controller that prints it over serial, one line per state.
+/// The original format is just a `'0'`/`'1'` character for every button:
+///
+/// `0010000010000000000000\n`
+///
+/// Newer firmware can prefix the line with a version, and then follow the buttons with
+/// the stick and trigger values it's sending to the console:
+///
+/// `v2 0010000010000000000000 128 255 128 128 0 140\n`
+///
+/// Those are the main stick x/y, c-stick x/y, then L and R, each from 0 to 255 with
+/// sticks centered on 128 and up being bigger (like a gamecube controller). Layouts
+/// refer to them by the same indices (or names) as the dolphin backend's analog values.
+///
+/// Instead of text, firmware can also send binary frames (which can be mixed with
+/// lines, since the sync byte isn't ascii):
+///
+/// - the sync byte `0xA5`
+/// - the length of the payload, 4 or 10
+/// - the buttons as a little endian `u32`, optionally followed by the 6 analog bytes
+/// - CRC-16/CCITT-FALSE of the length and payload, big endian
 #[derive(Debug)]
 pub struct Haybox {
-    ids: Vec<u8>,
-    rx: Receiver<u64>,
+    mapping: Mapping,
+    rx: Receiver<Message>,
+    connected: bool,
+}
+
+/// how long to wait before trying to reopen a port that went away, doubled after every
+/// failed attempt
+const MIN_BACKOFF: Duration = Duration::from_millis(250);
+const MAX_BACKOFF: Duration = Duration::from_secs(5);
+/// how often to complain about bad data
+const STATS_INTERVAL: Duration = Duration::from_secs(5);
+const SYNC: u8 = 0xA5;
+/// length byte, the longest payload, and the crc
+const MAX_FRAME: usize = 1 + 10 + 2;
+/// the longest line that could parse is `v2 `, 64 buttons, 6 analog values and `\r\n`
+const MAX_LINE: usize = 96;
+/// what the sticks and triggers read when the controller doesn't send them
+const NEUTRAL: [u8; 6] = [128, 128, 128, 128, 0, 0];
+
+/// Names for the buttons in the order that haybox firmware prints them. The gamecube
+/// ones also get the standard names from [`crate::melee::BUTTONS`], and
+/// `up`/`down`/`left`/`right` are the main stick's directions.
+#[rustfmt::skip]
+const BUTTONS: &[(&str, u8)] = &[
+    ("start", 0), ("y", 1), ("x", 2), ("b", 3), ("a", 4), ("l", 5), ("r", 6), ("z", 7),
+    ("up", 8), ("down", 9), ("right", 10), ("left", 11), ("mod_x", 12), ("mod_y", 13),
+    ("c_left", 14), ("c_right", 15), ("c_up", 16), ("c_down", 17),
+    ("light_shield", 18), ("mid_shield", 19), ("dpad_toggle", 20),
+    ("north", 1), ("east", 2), ("west", 3), ("south", 4),
+    ("left_trigger", 5), ("right_trigger", 6), ("right_bumper", 7),
+];
+
+#[derive(Debug)]
+enum Message {
+    State(Controller),
+    Connected(bool),
+}
+
+/// What to look for when the port needs to be reopened. USB adapters don't always come
+/// back with the same name, so if we know which device it was we look for that too.
+#[derive(Debug)]
+struct PortId {
+    path: String,
+    rate: u32,
+    usb: Option<(u16, u16, Option<String>)>,
+}
+
+impl PortId {
+    fn new(path: String, rate: u32) -> Self {
+        let usb = serialport::available_ports().ok().and_then(|ports| {
+            ports.into_iter().find(|p| p.port_name == path).and_then(|p| {
+                match p.port_type {
+                    SerialPortType::UsbPort(i) => Some((i.vid, i.pid, i.serial_number)),
+                    _ => None,
+                }
+            })
+        });
+        Self { path, rate, usb }
+    }
+
+    fn open(&self, path: &str) -> serialport::Result<Box<dyn SerialPort>> {
+        serialport::new(path, self.rate)
+            .flow_control(FlowControl::Hardware)
+            .timeout(Duration::from_millis(100))
+            .open()
+    }
+
+    /// tries the original path first, then anything with the same vid/pid/serial
+    fn reopen(&self) -> Option<(String, Box<dyn SerialPort>)> {
+        if let Ok(port) = self.open(&self.path) {
+            return Some((self.path.clone(), port));
+        }
+        let usb = self.usb.as_ref()?;
+        serialport::available_ports().ok()?.into_iter().find_map(|p| match p.port_type {
+            SerialPortType::UsbPort(i)
+                if (i.vid, i.pid) == (usb.0, usb.1) && i.serial_number == usb.2 =>
+            {
+                Some((p.port_name.clone(), self.open(&p.port_name).ok()?))
+            }
+            _ => None,
+        })
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+struct Stats {
+    states: u64,
+    /// frames with a bad length or crc, and lines that didn't parse
+    corrupt: u64,
+    /// bytes that had to be skipped to find the start of a frame or line (including lines
+    /// that got cut off or ran too long), and frames that got cut off
+    dropped: u64,
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let Self { states, corrupt, dropped } = self;
+        write!(f, "{states} states, {corrupt} corrupt, {dropped} dropped")
+    }
+}
+
+/// Splits a serial stream into states, whether it's sending lines or binary frames
+struct Reader<R> {
+    inner: BufReader<R>,
+    /// a line that got interrupted by a timeout
+    line: Vec<u8>,
+    stats: Stats,
+}
+
+impl<R: Read> Reader<R> {
+    fn new(inner: R) -> Self {
+        Self {
+            inner: BufReader::new(inner),
+            line: Vec::with_capacity(MAX_LINE),
+            stats: Stats::default(),
+        }
+    }
+
+    /// `Ok(None)` means something got thrown away, timeouts are passed through as errors
+    fn next(&mut self) -> io::Result<Option<Controller>> {
+        if self.line.is_empty() {
+            let first = *self.inner.fill_buf()?.first().ok_or(ErrorKind::UnexpectedEof)?;
+            match first {
+                SYNC => {
+                    self.inner.consume(1);
+                    return self.frame();
+                }
+                b'0' | b'1' | b'v' => {}
+                _ => {
+                    self.inner.consume(1);
+                    self.stats.dropped += 1;
+                    return Ok(None);
+                }
+            }
+        }
+
+        // a sync byte means the line was cut off by a frame (or was never a line, just the
+        // leftovers of a bad frame), so give up on it rather than swallowing the frame
+        loop {
+            let buf = self.inner.fill_buf()?;
+            if buf.is_empty() {
+                return Err(ErrorKind::UnexpectedEof.into());
+            }
+            let end = buf.iter().position(|&b| b == b'\n' || b == SYNC);
+            let take = end.map_or(buf.len(), |i| i + usize::from(buf[i] == b'\n'));
+            self.line.extend_from_slice(&buf[..take]);
+            self.inner.consume(take);
+            if self.line.ends_with(b"\n") {
+                break;
+            }
+            if end.is_some() || self.line.len() > MAX_LINE {
+                debug!("Dropping partial line from serial: {}", self.line.escape_ascii());
+                self.stats.dropped += self.line.len() as u64;
+                self.line.clear();
+                return Ok(None);
+            }
+        }
+        let state = parse_line(&self.line);
+        match state {
+            Some(_) => self.stats.states += 1,
+            None => {
+                debug!("Couldn't parse line from serial: {}", self.line.escape_ascii());
+                self.stats.corrupt += 1;
+            }
+        }
+        self.line.clear();
+        Ok(state)
+    }
+
+    fn frame(&mut self) -> io::Result<Option<Controller>> {
+        let mut frame = [0u8; MAX_FRAME];
+        if let Err(e) = self.inner.read_exact(&mut frame[..1]) {
+            return self.cut_off(e);
+        }
+        let len = frame[0] as usize;
+        if !matches!(len, 4 | 10) {
+            self.stats.corrupt += 1;
+            return Ok(None);
+        }
+        if let Err(e) = self.inner.read_exact(&mut frame[1..len + 3]) {
+            return self.cut_off(e);
+        }
+        let (data, crc) = frame[..len + 3].split_at(len + 1);
+        if crc16(data).to_be_bytes() != crc {
+            self.stats.corrupt += 1;
+            return Ok(None);
+        }
+        self.stats.states += 1;
+        let (buttons, analog) = data[1..].split_at(4);
+        let buttons = u32::from_le_bytes(buttons.try_into().unwrap()) as u64;
+        Ok(Some(controller(buttons, analog.try_into().unwrap_or(NEUTRAL))))
+    }
+
+    /// a frame that got interrupted by a timeout is dropped, anything else is fatal
+    fn cut_off(&mut self, e: io::Error) -> io::Result<Option<Controller>> {
+        if e.kind() != ErrorKind::TimedOut {
+            return Err(e);
+        }
+        self.stats.dropped += 1;
+        Ok(None)
+    }
+}
+
+/// Reads states until the port errors out, returns false if the receiver is gone
+fn read_port(port: impl Read, tx: &Sender<Message>) -> bool {
+    let mut reader = Reader::new(port);
+    let mut reported = (Instant::now(), Stats::default());
+    loop {
+        let state = match reader.next() {
+            Ok(state) => state,
+            // the port just didn't send anything before the timeout
+            Err(e) if e.kind() == ErrorKind::TimedOut => None,
+            Err(_) => {
+                info!("Serial stats: {}", reader.stats);
+                return true;
+            }
+        };
+        if let Some(state) = state
+            && tx.send(Message::State(state)).is_err()
+        {
+            return false;
+        }
+
+        let (last, old) = reported;
+        let new = reader.stats;
+        if (new.corrupt, new.dropped) != (old.corrupt, old.dropped)
+            && last.elapsed() > STATS_INTERVAL
+        {
+            warn!("Bad data from serial port, {new}");
+            reported = (Instant::now(), new);
+        }
+    }
+}
+
+/// CRC-16/CCITT-FALSE (polynomial 0x1021, starting from 0xFFFF)
+fn crc16(data: &[u8]) -> u16 {
+    let mut crc = 0xFFFFu16;
+    for &b in data {
+        crc ^= (b as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 { crc << 1 ^ 0x1021 } else { crc << 1 };
+        }
+    }
+    crc
+}
+
+fn controller(buttons: u64, [lx, ly, cx, cy, l, r]: [u8; 6]) -> Controller {
+    let stick = |v: u8| ((v as f32 - 128.0) / 127.0).clamp(-1.0, 1.0);
+    Controller {
+        buttons,
+        analog: [
+            stick(lx),
+            -stick(ly),
+            stick(cx),
+            -stick(cy),
+            l as f32 / 255.0,
+            r as f32 / 255.0,
+        ],
+    }
+}
+
+/// Returns `None` for anything other than `'0'`/`'1'` (or more than 64 of them), so that
+/// garbage doesn't turn into a bunch of pressed buttons
+fn parse_buttons(bits: &[u8]) -> Option<u64> {
+    if bits.len() > u64::BITS as usize {
+        return None;
+    }
+    let mut buttons = 0;
+    for (i, &b) in bits.iter().enumerate() {
+        match b {
+            b'0' => {}
+            b'1' => buttons |= 1 << i,
+            _ => return None,
+        }
+    }
+    Some(buttons)
+}
+
+fn parse_line(line: &[u8]) -> Option<Controller> {
+    let line = line.strip_suffix(b"\n").unwrap_or(line);
+    let line = line.strip_suffix(b"\r").unwrap_or(line);
+    let Some(rest) = line.strip_prefix(b"v2 ") else {
+        return Some(controller(parse_buttons(line)?, NEUTRAL));
+    };
+
+    let mut fields = rest.split(|&b| b == b' ').filter(|f| !f.is_empty());
+    let buttons = parse_buttons(fields.next()?)?;
+    let mut values = [0u8; 6];
+    for v in &mut values {
+        *v = std::str::from_utf8(fields.next()?).ok()?.parse().ok()?;
+    }
+    fields.next().is_none().then(|| controller(buttons, values))
 }
 
 impl Backend for Haybox {
@@ -23,65 +338,86 @@ impl Backend for Haybox {
     type Err = Report;
 
     fn init((path, rate): Self::InitState, inputs: &Inputs) -> Result<Self, Self::Err> {
-        let port = serialport::new(&path, rate)
-            .flow_control(FlowControl::Hardware)
-            .timeout(Duration::from_millis(100))
-            .open()
-            .with_context(|| format!("Port '{path}' not available"))?;
+        let id = PortId::new(path, rate);
+        let port = id
+            .open(&id.path)
+            .with_context(|| format!("Port '{}' not available", id.path))?;
 
-        let ids = inputs.buttons.iter().map(|b| b.id).collect();
         let (tx, rx) = mpsc::channel();
 
         thread::spawn(move || {
-            let mut buf = vec![0u8; 28];
-            let mut reader = BufReader::new(port);
-            while reader.read_until(b'\n', &mut buf).is_ok() {
-                let mut temp: u64 = 0;
-                for (i, &b) in buf.iter().enumerate() {
-                    if b == b'1' {
-                        temp |= 1 << i;
-                    } else if b == b'\n' {
-                        break;
+            let mut port = Some(port);
+            let mut backoff = MIN_BACKOFF;
+            loop {
+                if let Some(port) = port.take() {
+                    if !read_port(port, &tx) {
+                        return;
                     }
+                    warn!("Lost serial port '{}', waiting for it to come back", id.path);
+                    backoff = MIN_BACKOFF;
                 }
-                if tx.send(temp).is_err() {
-                    break;
+                // this doubles as a check that the backend hasn't been dropped
+                if tx.send(Message::Connected(false)).is_err() {
+                    return;
+                }
+                thread::sleep(backoff);
+                match id.reopen() {
+                    Some((path, p)) => {
+                        info!("Reconnected to serial port '{path}'");
+                        if tx.send(Message::Connected(true)).is_err() {
+                            return;
+                        }
+                        port = Some(p);
+                    }
+                    None => {
+                        backoff = (backoff * 2).min(MAX_BACKOFF);
+                        debug!("'{}' still isn't back, retrying in {backoff:?}", id.path);
+                    }
                 }
-                buf.clear()
             }
         });
-        Ok(Haybox { ids, rx })
+        Ok(Haybox { mapping: Mapping::new(inputs, BUTTONS), rx, connected: true })
     }
 
     fn poll(&mut self, state: &mut InputState) -> bool {
         let mut changed = false;
-        let mut latest = match self.rx.try_recv() {
-            Ok(n) => n,
-            Err(e) => {
-                if e == TryRecvError::Disconnected {
-                    error!("Serial reader thread died");
+        let mut latest = None;
+        let mut i = 0;
+        loop {
+            match self.rx.try_recv() {
+                Ok(Message::State(n)) => {
+                    latest = Some(n);
+                    i += 1;
+                }
+                Ok(Message::Connected(connected)) => {
+                    changed |= self.connected != connected;
+                    self.connected = connected;
+                }
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    if self.connected {
+                        error!("Serial reader thread died");
+                        self.connected = false;
+                    }
+                    break;
                 }
-                return false;
             }
-        };
-        let mut i = 0;
-        while let Ok(n) = self.rx.try_recv() {
-            latest = n;
-            i += 1;
         }
-        trace!("found {i} extra states");
+        let Some(latest) = latest else { return changed };
+        trace!("found {} extra states", i - 1);
+        self.mapping.apply(&latest, state) || changed
+    }
 
-        for (i, n) in self.ids.iter().enumerate() {
-            let new = latest & 1 << n != 0;
-            let old = state.buttons[i];
-            state.buttons[i] = new;
-            changed |= new != old;
-        }
-        changed
+    fn connected(&self) -> bool {
+        self.connected
+    }
+
+    fn missing_ids(&self) -> &[MissingId] {
+        &self.mapping.missing
     }
 
     fn reload(&mut self, inputs: &Inputs) {
-        self.ids = inputs.buttons.iter().map(|b| b.id).collect();
+        self.mapping = Mapping::new(inputs, BUTTONS);
     }
 }
 
@@ -112,3 +448,190 @@ pub fn get_ports() -> HashMap<String, String> {
     }
     all_ports
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::VecDeque, fs::File, io::Write, os::fd::FromRawFd, ptr};
+
+    use super::*;
+
+    fn frame(payload: &[u8]) -> Vec<u8> {
+        let mut frame = vec![SYNC, payload.len() as u8];
+        frame.extend_from_slice(payload);
+        let crc = crc16(&frame[1..]);
+        frame.extend_from_slice(&crc.to_be_bytes());
+        frame
+    }
+
+    /// Reads everything until the end, leaving out timeouts
+    fn read_all(reader: &mut Reader<impl Read>) -> Vec<Option<Controller>> {
+        let mut states = Vec::new();
+        loop {
+            match reader.next() {
+                Ok(state) => states.push(state),
+                Err(e) if e.kind() == ErrorKind::TimedOut => {}
+                Err(e) if e.kind() == ErrorKind::UnexpectedEof => return states,
+                Err(e) => panic!("{e}"),
+            }
+        }
+    }
+
+    /// Hands out each chunk in its own read, with an empty one meaning a timeout
+    struct Chunks(VecDeque<Vec<u8>>);
+
+    impl Read for Chunks {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let Some(chunk) = self.0.front_mut() else { return Ok(0) };
+            if chunk.is_empty() {
+                self.0.pop_front();
+                return Err(ErrorKind::TimedOut.into());
+            }
+            let n = buf.len().min(chunk.len());
+            buf[..n].copy_from_slice(&chunk[..n]);
+            chunk.drain(..n);
+            if chunk.is_empty() {
+                self.0.pop_front();
+            }
+            Ok(n)
+        }
+    }
+
+    #[test]
+    fn crc_check_value() {
+        assert_eq!(crc16(b"123456789"), 0x29B1);
+    }
+
+    #[test]
+    fn frames_mixed_with_lines() {
+        let mut data = frame(&0b10001u32.to_le_bytes());
+        data.extend_from_slice(b"v2 0010000010000000000000 128 255 128 128 0 140\r\n");
+        data.extend(frame(&[2, 0, 0, 0, 255, 128, 128, 0, 255, 0]));
+        data.extend_from_slice(b"0100\n");
+        let mut reader = Reader::new(data.as_slice());
+        let states = read_all(&mut reader);
+        assert_eq!(
+            states,
+            [
+                Some(controller(0b10001, NEUTRAL)),
+                Some(controller(1 << 2 | 1 << 8, [128, 255, 128, 128, 0, 140])),
+                Some(controller(0b10, [255, 128, 128, 0, 255, 0])),
+                Some(controller(0b10, NEUTRAL)),
+            ]
+        );
+        assert_eq!(reader.stats, Stats { states: 4, corrupt: 0, dropped: 0 });
+        assert_eq!(states[1].unwrap().analog[1], -1.0);
+    }
+
+    #[test]
+    fn bad_frames_and_lines_are_corrupt() {
+        let mut bad_crc = frame(&[1, 0, 0, 0]);
+        *bad_crc.last_mut().unwrap() ^= 1;
+        let mut data = bad_crc;
+        // a length that no frame has
+        data.extend_from_slice(&[SYNC, 7]);
+        data.extend_from_slice(b"0120\nv2 01 128\n");
+        data.extend(frame(&[1, 0, 0, 0]));
+        let mut reader = Reader::new(data.as_slice());
+        assert_eq!(
+            read_all(&mut reader),
+            [None, None, None, None, Some(controller(1, NEUTRAL))]
+        );
+        assert_eq!(reader.stats, Stats { states: 1, corrupt: 4, dropped: 0 });
+    }
+
+    #[test]
+    fn resyncs_after_bad_frames() {
+        // the rest of a frame with a bad length looks like the start of a line
+        let mut data = vec![SYNC, 11, b'0', b'1', b'v'];
+        data.extend(frame(&[1, 0, 0, 0]));
+        data.extend(frame(&[2, 0, 0, 0]));
+        let mut reader = Reader::new(data.as_slice());
+        assert_eq!(
+            read_all(&mut reader),
+            [None, None, Some(controller(1, NEUTRAL)), Some(controller(2, NEUTRAL))]
+        );
+        assert_eq!(reader.stats, Stats { states: 2, corrupt: 1, dropped: 3 });
+
+        // and a line that never ends gets thrown away before it grows too big
+        let chunks = [vec![b'1'; MAX_LINE + 4], vec![], frame(&[4, 0, 0, 0])];
+        let mut reader = Reader::new(Chunks(chunks.into()));
+        assert_eq!(read_all(&mut reader), [None, Some(controller(4, NEUTRAL))]);
+        assert_eq!(
+            reader.stats,
+            Stats { states: 1, corrupt: 0, dropped: MAX_LINE as u64 + 4 }
+        );
+    }
+
+    #[test]
+    fn garbage_is_dropped() {
+        let mut reader = Reader::new(b"\0\xff 0011\n".as_slice());
+        assert_eq!(read_all(&mut reader).into_iter().flatten().count(), 1);
+        assert_eq!(reader.stats, Stats { states: 1, corrupt: 0, dropped: 3 });
+        assert_eq!(reader.stats.to_string(), "1 states, 0 corrupt, 3 dropped");
+    }
+
+    #[test]
+    fn timeouts() {
+        let good = frame(&[4, 0, 0, 0]);
+        let chunks = [
+            // a frame that gets cut off is thrown away
+            good[..5].to_vec(),
+            vec![],
+            // but a line keeps going after the timeout
+            b"00".to_vec(),
+            vec![],
+            b"01\n".to_vec(),
+            good,
+        ];
+        let mut reader = Reader::new(Chunks(chunks.into()));
+        let states = read_all(&mut reader);
+        assert_eq!(
+            states,
+            [None, Some(controller(0b1000, NEUTRAL)), Some(controller(4, NEUTRAL))]
+        );
+        assert_eq!(reader.stats, Stats { states: 2, corrupt: 0, dropped: 1 });
+    }
+
+    #[test]
+    fn all_64_buttons() {
+        let mut line = [b'0'; 64];
+        line[63] = b'1';
+        assert_eq!(parse_buttons(&line), Some(1 << 63));
+        assert_eq!(parse_buttons(&[b'0'; 65]), None);
+    }
+
+    /// The same as the others, but through a real (pseudo) terminal like the serial port
+    #[test]
+    fn over_a_pty() {
+        // SAFETY: the fds come straight from openpty and nothing else owns them
+        let (mut master, slave) = unsafe {
+            let (mut master, mut slave) = (0, 0);
+            let res = libc::openpty(
+                &mut master,
+                &mut slave,
+                ptr::null_mut(),
+                ptr::null(),
+                ptr::null(),
+            );
+            assert_eq!(res, 0, "{}", io::Error::last_os_error());
+            // otherwise the terminal mangles the binary frames
+            let mut termios = std::mem::zeroed();
+            assert_eq!(libc::tcgetattr(slave, &mut termios), 0);
+            libc::cfmakeraw(&mut termios);
+            assert_eq!(libc::tcsetattr(slave, libc::TCSANOW, &termios), 0);
+            (File::from_raw_fd(master), File::from_raw_fd(slave))
+        };
+        let mut data = frame(&[1, 2, 0, 0, 0, 255, 128, 128, 0, 0]);
+        data.extend_from_slice(b"v2 1 128 128 128 128 255 0\n");
+        master.write_all(&data).unwrap();
+
+        let mut reader = Reader::new(slave);
+        let first = reader.next().unwrap().unwrap();
+        assert_eq!(first, controller(0x201, [0, 255, 128, 128, 0, 0]));
+        assert_eq!(
+            reader.next().unwrap(),
+            Some(controller(1, [128, 128, 128, 128, 255, 0]))
+        );
+        assert_eq!(reader.stats, Stats { states: 2, corrupt: 0, dropped: 0 });
+    }
+}
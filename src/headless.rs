@@ -0,0 +1,177 @@
+//! Drawing a layout straight to a png without opening a window, for screenshots and
+//! previews of layouts
+
+use std::{fs, path::PathBuf};
+
+use clap::Args;
+use color_eyre::{
+    Report,
+    eyre::{Context, bail, eyre},
+};
+
+use tiny_skia::Pixmap;
+
+use crate::config::{self, Id};
+use crate::gamepad::{Direction, Directions, Gamepad, InputState};
+
+/// Draw a layout to a png without opening a window
+#[derive(Debug, Args)]
+pub struct Options {
+    /// The layout to draw
+    pub layout: PathBuf,
+    /// Where to write the png
+    pub output: PathBuf,
+    /// An InputState to start from, like {"buttons": [true], "sticks": [[0.5, -1]]}
+    #[arg(long, value_name = "FILE.json")]
+    pub state: Option<PathBuf>,
+    /// Press the buttons with these ids, like 0,1 or south,start
+    #[arg(long, value_name = "ID,ID,...", value_delimiter = ',')]
+    pub buttons: Vec<Id>,
+    /// Move a stick, -1..1 with down being positive
+    #[arg(long, value_name = "INDEX=X,Y", value_parser = parse_stick)]
+    pub stick: Vec<(usize, (f32, f32))>,
+    /// Set an axis, 0..1
+    #[arg(long, value_name = "INDEX=VALUE", value_parser = parse_axis)]
+    pub axis: Vec<(usize, f32)>,
+    /// Press directions on a dpad, like 0=up+left
+    #[arg(long, value_name = "INDEX=DIRS", value_parser = parse_dpad)]
+    pub dpad: Vec<(usize, Directions)>,
+    /// Draw it like the controller is unplugged
+    #[arg(long)]
+    pub disconnected: bool,
+}
+
+/// Splits `index=value`, the indices are into the layout's sticks/axes/dpads
+fn indexed(arg: &str) -> Result<(usize, &str), String> {
+    let (i, value) = arg.split_once('=').ok_or("expected INDEX=VALUE")?;
+    Ok((i.trim().parse().map_err(|_| format!("bad index {i}"))?, value))
+}
+
+fn parse_stick(arg: &str) -> Result<(usize, (f32, f32)), String> {
+    let (i, pos) = indexed(arg)?;
+    let parsed = pos
+        .split_once(',')
+        .and_then(|(x, y)| Some((x.trim().parse().ok()?, y.trim().parse().ok()?)));
+    Ok((i, parsed.ok_or_else(|| format!("bad stick position {pos}"))?))
+}
+
+fn parse_axis(arg: &str) -> Result<(usize, f32), String> {
+    let (i, value) = indexed(arg)?;
+    Ok((i, value.trim().parse().map_err(|_| format!("bad axis value {value}"))?))
+}
+
+fn parse_dpad(arg: &str) -> Result<(usize, Directions), String> {
+    let (i, dirs) = indexed(arg)?;
+    let mut pressed = Directions::default();
+    for dir in dirs.split('+') {
+        let dir = match dir.trim() {
+            "up" => Direction::Up,
+            "down" => Direction::Down,
+            "left" => Direction::Left,
+            "right" => Direction::Right,
+            "" => continue,
+            _ => return Err(format!("bad dpad direction {dir}")),
+        };
+        pressed.set(dir, true);
+    }
+    Ok((i, pressed))
+}
+
+pub fn render(options: &Options) -> Result<(), Report> {
+    draw(options)?
+        .save_png(&options.output)
+        .with_context(|| format!("Couldn't write {}", options.output.display()))
+}
+
+fn draw(options: &Options) -> Result<Pixmap, Report> {
+    let (config, _) = config::Gamepad::from_file(&options.layout)?;
+    let mut gamepad = Gamepad::default();
+    gamepad.reload(&config);
+    let state = &mut gamepad.input_state;
+    state.connected = true;
+
+    // anything that doesn't line up with the layout is ignored
+    if let Some(path) = &options.state {
+        let json = fs::read_to_string(path)
+            .with_context(|| format!("Couldn't read {}", path.display()))?;
+        let loaded: InputState = serde_json::from_str(&json)
+            .with_context(|| format!("Couldn't parse {}", path.display()))?;
+        state.buttons.iter_mut().zip(loaded.buttons).for_each(|(a, b)| *a = b);
+        state.sticks.iter_mut().zip(loaded.sticks).for_each(|(a, b)| *a = b);
+        state.axes.iter_mut().zip(loaded.axes).for_each(|(a, b)| *a = b);
+        state.dpads.iter_mut().zip(loaded.dpads).for_each(|(a, b)| *a = b);
+        state.connected = loaded.connected;
+    }
+
+    for id in &options.buttons {
+        let buttons = gamepad.inputs.buttons.iter().zip(&mut state.buttons);
+        let mut found = false;
+        for (_, pressed) in buttons.filter(|(b, _)| b.id == *id) {
+            *pressed = true;
+            found = true;
+        }
+        if !found {
+            bail!("button {id} isn't in the layout");
+        }
+    }
+    for &(i, pos) in &options.stick {
+        *state.sticks.get_mut(i).ok_or_else(|| eyre!("there's no stick {i}"))? = pos;
+    }
+    for &(i, value) in &options.axis {
+        *state.axes.get_mut(i).ok_or_else(|| eyre!("there's no axis {i}"))? = value;
+    }
+    for &(i, dirs) in &options.dpad {
+        *state.dpads.get_mut(i).ok_or_else(|| eyre!("there's no dpad {i}"))? = dirs;
+    }
+    if options.disconnected {
+        state.connected = false;
+    }
+
+    let mut img = crate::create_image(&gamepad.inputs);
+    gamepad.render(&mut img);
+    Ok(img)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, path::Path};
+
+    use super::*;
+
+    /// Renders the example layout with a bit of everything pressed and compares it to
+    /// the checked in png. Run with `UPDATE_GOLDEN=1` to write a new one after changing
+    /// the example or how things are drawn.
+    #[test]
+    fn example_layout() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let golden = dir.join("tests/golden/example.png");
+        let options = Options {
+            layout: dir.join("layouts/example.toml"),
+            output: PathBuf::new(),
+            state: None,
+            buttons: ["east", "left_trigger", "start", "13"]
+                .map(|id| id.parse().unwrap())
+                .into(),
+            stick: vec![(0, (0.7, -0.7)), (1, (-1.0, 0.0))],
+            axis: Vec::new(),
+            dpad: vec![(0, Directions { down: true, right: true, ..Default::default() })],
+            disconnected: false,
+        };
+        let img = draw(&options).unwrap();
+        if env::var_os("UPDATE_GOLDEN").is_some() {
+            img.save_png(&golden).unwrap();
+        }
+
+        let expected = Pixmap::load_png(&golden).unwrap();
+        assert_eq!((img.width(), img.height()), (expected.width(), expected.height()));
+        // a little leeway for float differences between platforms
+        let different = (img.data().iter().zip(expected.data()))
+            .filter(|(a, b)| a.abs_diff(**b) > 2)
+            .count();
+        if different > 0 {
+            let actual = env::temp_dir().join("example.png");
+            img.save_png(&actual).unwrap();
+            panic!("{different} channels don't match the golden image, see {actual:?}");
+        }
+    }
+}
@@ -0,0 +1,303 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::PathBuf,
+    str::FromStr,
+    sync::mpsc::{self, Receiver, TryRecvError},
+    thread,
+};
+
+use color_eyre::{
+    Report,
+    eyre::{Context, eyre},
+};
+use evdev::{Device, EventSummary, KeyCode};
+use log::{error, info, warn};
+
+use crate::{
+    config::Id,
+    gamepad::{Backend, DpadSource, InputState, Inputs, MissingId},
+};
+
+/// Reads keys straight from evdev, so it works without a focused window
+#[derive(Debug)]
+pub struct Keyboard {
+    rx: Receiver<(KeyCode, bool)>,
+    /// there's no standard mapping, so names are just matched against the layout's ids
+    keys: HashMap<KeyCode, Id>,
+    /// how many of the keys for each id are held
+    held: HashMap<Id, usize>,
+    buttons: Vec<Id>,
+    dpads: Vec<Option<[Id; 4]>>,
+    /// ids in the layout that none of the `[keys]` are bound to
+    missing: Vec<MissingId>,
+    connected: bool,
+}
+
+/// Anything that has regular keys (as opposed to only `BTN_*` codes) counts, which
+/// includes most hitbox style controllers that pretend to be keyboards
+fn is_keyboard(device: &Device) -> bool {
+    device.supported_keys().is_some_and(|keys| keys.iter().any(|k| k.code() < 0x100))
+}
+
+/// returns the path and name of all the keyboards that we have permission to read
+pub fn get_devices() -> Vec<(PathBuf, String)> {
+    let mut devices: Vec<_> = evdev::enumerate()
+        .filter(|(_, d)| is_keyboard(d))
+        .map(|(path, d)| (path, d.name().unwrap_or("(???)").to_owned()))
+        .collect();
+    devices.sort();
+    devices
+}
+
+impl Backend for Keyboard {
+    /// a specific `/dev/input/event*` device, or `None` for every keyboard
+    type InitState = Option<PathBuf>;
+    type Err = Report;
+
+    fn init(path: Self::InitState, inputs: &Inputs) -> Result<Self, Self::Err> {
+        let devices = match path {
+            Some(path) => vec![(
+                path.clone(),
+                Device::open(&path).with_context(|| {
+                    format!("couldn't open {path:?}, are you in the `input` group?")
+                })?,
+            )],
+            None => evdev::enumerate().filter(|(_, d)| is_keyboard(d)).collect(),
+        };
+        if devices.is_empty() {
+            return Err(eyre!(
+                "couldn't find any keyboards, are you in the `input` group?"
+            ));
+        }
+
+        let (tx, rx) = mpsc::channel();
+        for (path, mut device) in devices {
+            info!("reading keys from {} ({path:?})", device.name().unwrap_or_default());
+            let tx = tx.clone();
+            thread::spawn(move || {
+                loop {
+                    let events = match device.fetch_events() {
+                        Ok(events) => events,
+                        Err(e) => {
+                            error!("stopped reading {path:?}: {e}");
+                            return;
+                        }
+                    };
+                    for ev in events {
+                        // 2 means the key is repeating
+                        if let EventSummary::Key(_, code, value @ (0 | 1)) =
+                            ev.destructure()
+                            && tx.send((code, value == 1)).is_err()
+                        {
+                            return;
+                        }
+                    }
+                }
+            });
+        }
+
+        let mut keyboard = Self {
+            rx,
+            keys: HashMap::new(),
+            held: HashMap::new(),
+            buttons: Vec::new(),
+            dpads: Vec::new(),
+            missing: Vec::new(),
+            connected: true,
+        };
+        keyboard.reload(inputs);
+        Ok(keyboard)
+    }
+
+    fn poll(&mut self, state: &mut InputState) -> bool {
+        let mut changed = false;
+        loop {
+            match self.rx.try_recv() {
+                Ok((code, pressed)) => {
+                    let Some(id) = self.keys.get(&code) else { continue };
+                    let held = self.held.entry(id.clone()).or_default();
+                    *held = if pressed { *held + 1 } else { held.saturating_sub(1) };
+                    changed = true;
+                }
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    if self.connected {
+                        error!("All keyboard reader threads died");
+                        self.connected = false;
+                    }
+                    break;
+                }
+            }
+        }
+        if !changed {
+            return false;
+        }
+
+        let held = |id| self.held.get(id).is_some_and(|&n| n > 0);
+        for (id, pressed) in self.buttons.iter().zip(&mut state.buttons) {
+            *pressed = held(id);
+        }
+        for (ids, dirs) in self.dpads.iter().zip(&mut state.dpads) {
+            if let Some([up, down, left, right]) = ids {
+                dirs.up = held(up);
+                dirs.down = held(down);
+                dirs.left = held(left);
+                dirs.right = held(right);
+            }
+        }
+        true
+    }
+
+    fn connected(&self) -> bool {
+        self.connected
+    }
+
+    fn missing_ids(&self) -> &[MissingId] {
+        &self.missing
+    }
+
+    fn reload(&mut self, inputs: &Inputs) {
+        self.keys = inputs
+            .keys
+            .iter()
+            .filter_map(|(name, id)| match KeyCode::from_str(name) {
+                Ok(code) => Some((code, id.clone())),
+                Err(_) => {
+                    warn!("Unknown key {name}, expected something like KEY_A");
+                    None
+                }
+            })
+            .collect();
+        self.held.clear();
+        self.buttons = inputs.buttons.iter().map(|b| b.id.clone()).collect();
+        self.dpads = inputs
+            .dpads
+            .iter()
+            .map(|d| match &d.source {
+                DpadSource::Buttons(ids) => Some(ids.clone()),
+                DpadSource::Hat(..) => None,
+            })
+            .collect();
+
+        let bound: HashSet<_> = self.keys.values().collect();
+        let buttons = self.buttons.iter().enumerate().map(|(i, id)| ("buttons", i, id));
+        let dpads = self
+            .dpads
+            .iter()
+            .enumerate()
+            .flat_map(|(i, ids)| ids.iter().flatten().map(move |id| ("dpads", i, id)));
+        self.missing = buttons
+            .chain(dpads)
+            .filter(|(_, _, id)| !bound.contains(id))
+            .map(|(key, index, id)| MissingId { key, index, id: id.clone() })
+            .collect();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        thread,
+        time::{Duration, Instant},
+    };
+
+    use evdev::{AttributeSet, KeyEvent, uinput::VirtualDevice};
+
+    use super::*;
+    use crate::{config, gamepad::Directions};
+
+    /// Needs write access to /dev/uinput, run with `cargo test -- --ignored`
+    #[test]
+    #[ignore]
+    fn reads_a_virtual_keyboard() {
+        let keys = [KeyCode::KEY_J, KeyCode::KEY_K, KeyCode::KEY_W, KeyCode::KEY_D];
+        let mut device = VirtualDevice::builder()
+            .unwrap()
+            .name("obs-gamepad test keyboard")
+            .with_keys(&AttributeSet::from_iter(keys))
+            .unwrap()
+            .build()
+            .unwrap();
+        let path = device.enumerate_dev_nodes_blocking().unwrap().next().unwrap().unwrap();
+
+        let layout: config::Gamepad = toml::from_str(
+            r#"
+            buttons = [{ id = "a", pos = [0, 0] }, { id = "b", pos = [50, 0] }]
+            dpads = [{ buttons = ["up", "down", "left", "right"], pos = [100, 0] }]
+            [keys]
+            KEY_J = "a"
+            KEY_K = "a"
+            KEY_W = "up"
+            KEY_D = "right"
+            "#,
+        )
+        .unwrap();
+        let inputs: Inputs = (&layout).into();
+        let mut keyboard = Keyboard::init(Some(path), &inputs).unwrap();
+        let mut state: InputState = (&inputs).into();
+        // the reader threads need a moment to start listening
+        thread::sleep(Duration::from_millis(100));
+
+        // returns the buttons and the dpad once the keys come through
+        let mut send = |events: &[(KeyCode, i32)]| {
+            let events: Vec<_> =
+                events.iter().map(|&(k, v)| *KeyEvent::new(k, v)).collect();
+            device.emit(&events).unwrap();
+            let start = Instant::now();
+            while !keyboard.poll(&mut state) {
+                assert!(start.elapsed() < Duration::from_secs(1), "no key events came in");
+                thread::sleep(Duration::from_millis(5));
+            }
+            (state.buttons.clone(), state.dpads[0])
+        };
+        let (buttons, dpad) =
+            send(&[(KeyCode::KEY_J, 1), (KeyCode::KEY_W, 1), (KeyCode::KEY_D, 1)]);
+        assert_eq!(buttons, [true, false]);
+        assert_eq!(dpad, Directions { up: true, right: true, ..Default::default() });
+
+        // both keys for `a` are held, so it's still pressed until they're both let go
+        let (buttons, dpad) =
+            send(&[(KeyCode::KEY_K, 1), (KeyCode::KEY_J, 0), (KeyCode::KEY_W, 0)]);
+        assert_eq!(buttons, [true, false]);
+        assert_eq!(dpad, Directions { right: true, ..Default::default() });
+        let (buttons, _) = send(&[(KeyCode::KEY_K, 0)]);
+        assert_eq!(buttons, [false, false]);
+    }
+
+    #[test]
+    fn unbound_ids_are_missing() {
+        let layout: config::Gamepad = toml::from_str(
+            r#"
+            buttons = [{ id = "a", pos = [0, 0] }, { id = 1, pos = [50, 0] }]
+            dpads = [
+                { buttons = ["up", "down", "left", "right"], pos = [100, 0] },
+                { axes = [6, 7], pos = [150, 0] },
+            ]
+            [keys]
+            KEY_J = "a"
+            KEY_W = "up"
+            KEY_S = "down"
+            KEY_D = "right"
+            "#,
+        )
+        .unwrap();
+        let inputs: Inputs = (&layout).into();
+        let mut keyboard = Keyboard {
+            rx: mpsc::channel().1,
+            keys: HashMap::new(),
+            held: HashMap::new(),
+            buttons: Vec::new(),
+            dpads: Vec::new(),
+            missing: Vec::new(),
+            connected: true,
+        };
+        keyboard.reload(&inputs);
+        assert_eq!(
+            keyboard.missing_ids(),
+            [
+                MissingId { key: "buttons", index: 1, id: Id::Index(1) },
+                MissingId { key: "dpads", index: 0, id: Id::Name("left".into()) },
+            ]
+        );
+    }
+}
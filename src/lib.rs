@@ -1,15 +1,24 @@
+mod check;
 mod config;
+mod controllerdb;
+#[cfg(target_os = "linux")]
+mod dolphin;
 mod gamepad;
 mod haybox;
+#[cfg(target_os = "linux")]
+mod keyboard;
+mod melee;
+mod slippi;
 mod usb;
+mod vars;
 
 use std::{
     borrow::Cow,
-    fs,
     path::{Path, PathBuf},
     time::Duration,
 };
 
+use color_eyre::{Report, eyre::eyre};
 use gilrs_core::{self, Gilrs};
 use haybox::Haybox;
 use log::{error, info};
@@ -22,6 +31,7 @@ use tiny_skia::Pixmap;
 
 use config::ConfigWatcher;
 use gamepad::{Backend, Gamepad, Inputs};
+use slippi::Slippi;
 use usb::UsbGamepad;
 
 obs_register_module!(GamepadModule);
@@ -48,6 +58,10 @@ impl From<&Inputs> for Image {
         let (width, height) = if inputs.buttons.is_empty()
             && inputs.axes.is_empty()
             && inputs.sticks.is_empty()
+            && inputs.dpads.is_empty()
+            && inputs.history.is_empty()
+            && inputs.readouts.is_empty()
+            && inputs.background.is_none()
         {
             (100, 100)
         } else {
@@ -68,23 +82,23 @@ impl<'b> Source<'b> {
     fn update_config(&mut self, path: &Path) {
         info!("config update");
         self.image.force_render = true;
-        if let Ok(contents) = fs::read_to_string(path) {
-            match toml::from_str(&contents) {
-                Ok(config) => {
-                    self.gamepad.reload(&config);
-                    let bounds = self.gamepad.inputs.bounds();
-                    if self.image.width != bounds.right() as u32
-                        || self.image.height != bounds.bottom() as u32
-                    {
-                        self.image = (&self.gamepad.inputs).into();
-                    }
+        match config::Gamepad::from_file(path) {
+            Ok((config, deps)) => {
+                self.gamepad.reload(&config);
+                if let Err(e) = self.watcher.set_dependencies(deps) {
+                    error!("Couldn't watch the files that the config uses: {e}");
                 }
-                Err(e) => {
-                    error!("Config reload failed: {}", e);
+                let bounds = self.gamepad.inputs.bounds();
+                if self.image.width != bounds.right() as u32
+                    || self.image.height != bounds.bottom() as u32
+                {
+                    self.image = (&self.gamepad.inputs).into();
                 }
             }
-        } else {
-            error!("Couldn't read {}", path.to_string_lossy())
+            Err(e) => {
+                error!("Config reload failed: {e:#}");
+                check::log(path);
+            }
         }
     }
 
@@ -94,27 +108,83 @@ impl<'b> Source<'b> {
             match if let Ok(n) = name.as_str().parse() {
                 UsbGamepad::init((Gilrs::new().unwrap(), n), &self.gamepad.inputs)
                     .map(|b| Box::new(b) as Box<_>)
+            } else if let Some(port) = name.as_str().strip_prefix(DOLPHIN_PREFIX) {
+                Self::init_dolphin(port, &self.gamepad.inputs)
+            } else if let Some(path) = name.as_str().strip_prefix(KEYBOARD_PREFIX) {
+                Self::init_keyboard(path, &self.gamepad.inputs)
+            } else if let Some(port) = name.as_str().strip_prefix(SLIPPI_PREFIX) {
+                Self::init_slippi(port, settings, &self.gamepad.inputs)
             } else {
                 Haybox::init((name.as_str().to_owned(), 115200), &self.gamepad.inputs)
                     .map(|b| Box::new(b) as Box<_>)
             } {
                 Ok(b) => self.gamepad.backend = Some(b),
-                Err(_) => error!("failed to load backend"),
+                Err(e) => error!("failed to load backend: {e}"),
             }
         }
         if let Some(path) = settings.get::<Cow<str>>(SETTING_FILE) {
             info!("changed config");
             let new = PathBuf::from(path.as_ref());
             if self.watcher.path.as_ref() != Some(&new) {
+                self.watcher.change_file(&new).unwrap();
                 self.update_config(&new);
-                self.watcher.change_file(new).unwrap();
             }
         }
     }
+
+    fn init_slippi(
+        port: &str,
+        settings: &DataObj,
+        inputs: &Inputs,
+    ) -> Result<Box<dyn Backend + 'b>, Report> {
+        let port = (!port.is_empty())
+            .then(|| port.parse().map_err(|_| eyre!("invalid replay port '{port}'")))
+            .transpose()?;
+        let replay = settings
+            .get::<Cow<str>>(SETTING_REPLAY)
+            .filter(|path| !path.is_empty())
+            .ok_or(eyre!("pick a replay file to play back"))?;
+        Slippi::init((PathBuf::from(replay.as_ref()), port), inputs)
+            .map(|b| Box::new(b) as Box<_>)
+    }
+
+    #[cfg(target_os = "linux")]
+    fn init_dolphin(port: &str, inputs: &Inputs) -> Result<Box<dyn Backend + 'b>, Report> {
+        let port = port.parse().map_err(|_| eyre!("invalid dolphin port '{port}'"))?;
+        dolphin::Dolphin::init(port, inputs).map(|b| Box::new(b) as Box<_>)
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn init_dolphin(
+        _port: &str,
+        _inputs: &Inputs,
+    ) -> Result<Box<dyn Backend + 'b>, Report> {
+        Err(eyre!("reading dolphin's memory is only supported on linux"))
+    }
+
+    #[cfg(target_os = "linux")]
+    fn init_keyboard(path: &str, inputs: &Inputs) -> Result<Box<dyn Backend + 'b>, Report> {
+        let path = (!path.is_empty()).then(|| PathBuf::from(path));
+        keyboard::Keyboard::init(path, inputs).map(|b| Box::new(b) as Box<_>)
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn init_keyboard(
+        _path: &str,
+        _inputs: &Inputs,
+    ) -> Result<Box<dyn Backend + 'b>, Report> {
+        Err(eyre!("the keyboard backend is only supported on linux"))
+    }
 }
 
 const SETTING_GAMEPAD: ObsString = obs_string!("gamepad");
 const SETTING_FILE: ObsString = obs_string!("settings");
+const SETTING_REPLAY: ObsString = obs_string!("replay");
+const DOLPHIN_PREFIX: &str = "dolphin:";
+/// followed by the path to a device, or nothing for every keyboard
+const KEYBOARD_PREFIX: &str = "keyboard:";
+/// followed by the port to play back, or nothing for the first player
+const SLIPPI_PREFIX: &str = "slippi:";
 
 impl<'b> Sourceable for Source<'b> {
     fn create(
@@ -149,11 +219,39 @@ impl GetPropertiesSource for Source<'_> {
         for (name, desc) in haybox::get_ports() {
             list.push(format!("{desc} ({name})"), name.into());
         }
+        #[cfg(target_os = "linux")]
+        for port in 1..=4 {
+            list.push(
+                format!("Dolphin (melee) port {port}"),
+                format!("{DOLPHIN_PREFIX}{port}").into(),
+            );
+        }
+        #[cfg(target_os = "linux")]
+        {
+            list.push("All keyboards".to_owned(), KEYBOARD_PREFIX.to_owned().into());
+            for (path, name) in keyboard::get_devices() {
+                let path = path.display();
+                list.push(
+                    format!("{name} ({path})"),
+                    format!("{KEYBOARD_PREFIX}{path}").into(),
+                );
+            }
+        }
+        list.push("Slippi replay".to_owned(), SLIPPI_PREFIX.to_owned().into());
+        for port in 1..=4 {
+            list.push(
+                format!("Slippi replay port {port}"),
+                format!("{SLIPPI_PREFIX}{port}").into(),
+            );
+        }
 
         let path_config = PathProp::new(PathType::File)
             .with_filter(obs_string!("TOML config file (*.toml)"));
         // TODO: set default pointing to the example.toml in the config dir
         props.add(SETTING_FILE, obs_string!("Layout File"), path_config);
+        let replay_config =
+            PathProp::new(PathType::File).with_filter(obs_string!("Slippi replay (*.slp)"));
+        props.add(SETTING_REPLAY, obs_string!("Replay File"), replay_config);
 
         props
     }
@@ -192,8 +290,10 @@ impl VideoRenderSource for Source<'_> {
         while let Ok(DebouncedEvent { path, kind: DebouncedEventKind::Any }) =
             self.watcher.rx.try_recv()
         {
-            if self.watcher.path.as_deref() == Some(&path) {
-                self.update_config(&path)
+            if self.watcher.is_relevant(&path)
+                && let Some(config) = self.watcher.path.clone()
+            {
+                self.update_config(&config)
             }
         }
         if self.gamepad.poll() || self.image.force_render {
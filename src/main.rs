@@ -1,64 +1,222 @@
+mod check;
 mod config;
+mod controllerdb;
+#[cfg(target_os = "linux")]
+mod dolphin;
+mod editor;
 mod gamepad;
 mod haybox;
+mod headless;
+#[cfg(target_os = "linux")]
+mod keyboard;
+mod melee;
+mod slippi;
 mod usb;
+mod vars;
 
 use std::io::Write;
+use std::path::PathBuf;
 use std::time::{Duration, Instant};
 use std::{fs, io};
 
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use color_eyre::Report;
 use gilrs_core::Gilrs;
 use haybox::Haybox;
 use log::{error, info};
-use minifb::{Key, ScaleMode, Window, WindowOptions};
+use minifb::{Key, KeyRepeat, ScaleMode, Window, WindowOptions};
 use notify_debouncer_mini::{DebouncedEvent, DebouncedEventKind};
 use tiny_skia::Pixmap;
 
 use config::ConfigWatcher;
-use gamepad::{Gamepad, Inputs};
+use editor::Editor;
+use gamepad::{Backend, Gamepad, Inputs};
+use slippi::Slippi;
 use usb::UsbGamepad;
 
-const FPS: usize = 60;
-const BENCHMARK: bool = false;
+/// melee runs at 60fps, so this is a second of a replay
+const SECOND: i64 = 60;
+
+/// Shows a layout in a window, reading inputs from a controller or a slippi replay.
+/// Without a device it'll ask which one to use.
+#[derive(Debug, Parser)]
+#[command(version, args_conflicts_with_subcommands = true)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+    /// The layout to show, the default shows every button and axis to help find ids
+    #[arg(default_value = "layouts/test.toml")]
+    layout: PathBuf,
+    /// A slippi replay to play back instead of reading from a device
+    #[arg(conflicts_with_all = ["backend", "device"])]
+    replay: Option<PathBuf>,
+    /// Which port in the replay to show, defaults to the first player
+    #[arg(requires = "replay")]
+    port: Option<u8>,
+    /// What kind of device to read from, defaults to whatever matches --device
+    #[arg(short, long, value_enum)]
+    backend: Option<BackendKind>,
+    /// The id, name, or path of the device (see --list-devices), defaults to the first
+    /// one for the backend
+    #[arg(short, long)]
+    device: Option<String>,
+    /// Baud rate for serial ports
+    #[arg(long, default_value_t = 115200)]
+    baud: u32,
+    /// How many times a second to check for inputs and redraw
+    #[arg(long, default_value_t = 60)]
+    fps: usize,
+    /// Print the devices that can be passed to --device and quit
+    #[arg(short, long)]
+    list_devices: bool,
+    /// Redraw every frame even if nothing changed, and log the average render time
+    #[arg(long)]
+    benchmark: bool,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    Render(headless::Options),
+    Check(CheckOptions),
+}
+
+/// List everything wrong with a layout, with where it is in the file. It fails if
+/// anything would stop the layout from loading.
+#[derive(Debug, Args)]
+struct CheckOptions {
+    /// The layout to check
+    layout: PathBuf,
+    /// What kind of device to check the ids against
+    #[arg(short, long, value_enum)]
+    backend: Option<BackendKind>,
+    /// A device (see --list-devices) to check that every id the layout uses is on it
+    #[arg(short, long)]
+    device: Option<String>,
+    /// Baud rate for serial ports
+    #[arg(long, default_value_t = 115200)]
+    baud: u32,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+enum BackendKind {
+    Usb,
+    Serial,
+    #[cfg(target_os = "linux")]
+    Dolphin,
+    #[cfg(target_os = "linux")]
+    Keyboard,
+}
+
+/// Something that a backend can read inputs from
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Device {
+    Usb(usize),
+    Serial(String),
+    #[cfg(target_os = "linux")]
+    Dolphin(u8),
+    /// `None` means every keyboard
+    #[cfg(target_os = "linux")]
+    Keyboard(Option<PathBuf>),
+}
+
+impl Device {
+    fn kind(&self) -> BackendKind {
+        match self {
+            Device::Usb(_) => BackendKind::Usb,
+            Device::Serial(_) => BackendKind::Serial,
+            #[cfg(target_os = "linux")]
+            Device::Dolphin(_) => BackendKind::Dolphin,
+            #[cfg(target_os = "linux")]
+            Device::Keyboard(_) => BackendKind::Keyboard,
+        }
+    }
+
+    /// What to pass to --device to pick this one
+    fn arg(&self) -> String {
+        match self {
+            Device::Usb(id) => id.to_string(),
+            Device::Serial(path) => path.clone(),
+            #[cfg(target_os = "linux")]
+            Device::Dolphin(port) => port.to_string(),
+            #[cfg(target_os = "linux")]
+            Device::Keyboard(None) => "all".into(),
+            #[cfg(target_os = "linux")]
+            Device::Keyboard(Some(path)) => path.display().to_string(),
+        }
+    }
+}
 
 fn main() -> Result<(), ()> {
     env_logger::init();
     color_eyre::install().unwrap();
+    let cli = Cli::parse();
+    if let Some(Command::Render(options)) = &cli.command {
+        return headless::render(options).map_err(|e| error!("Couldn't render: {e:#}"));
+    }
+    if let Some(Command::Check(options)) = &cli.command {
+        return check(options);
+    }
+
+    let gilrs = Gilrs::new().unwrap();
+    if cli.list_devices {
+        for (device, description) in list_devices(&gilrs) {
+            let kind = device.kind().to_possible_value().unwrap();
+            println!("{:<8} {:<24} {description}", kind.get_name(), device.arg());
+        }
+        return Ok(());
+    }
+
     let mut gamepad = Gamepad::default();
     let mut watcher = ConfigWatcher::new(Duration::from_millis(100));
-    let args: Vec<String> = std::env::args().skip(1).collect();
-    let arg = match args.as_slice() {
-        [] => "layouts/test.toml",
-        [path] => path,
-        [_, _, ..] => {
-            println!("Pass no args for debug mode, or just the path to a config file");
-            return Err(());
-        }
-    };
-    let watch_file = fs::canonicalize(arg).unwrap();
+    let watch_file = fs::canonicalize(&cli.layout)
+        .map_err(|e| error!("Couldn't open {}: {e}", cli.layout.display()))?;
     watcher.change_file(&watch_file).unwrap();
     let mut last_change = Instant::now();
 
-    let gilrs = Gilrs::new().unwrap();
-    let ports = serialport::available_ports().unwrap_or_default();
-    let max_gamepads = gilrs.last_gamepad_hint();
-    let id = pick_input(max_gamepads, &gilrs);
-
-    let config: Result<config::Gamepad, toml::de::Error> =
-        toml::from_str(&fs::read_to_string(&watch_file).unwrap());
-    if let Err(e) = config.map(|c| {
-        let res = if id < 10 {
-            gamepad.load::<UsbGamepad>(&c, (Gilrs::new().unwrap(), id))
-        } else {
-            let name =
-                &ports.get(id - 10).expect("couldn't find or open serial port").port_name;
-            gamepad.load::<Haybox>(&c, (name.clone(), 115200))
+    let replay = cli.replay.clone().map(|replay| (replay, cli.port));
+    let device = match (&replay, cli.backend, &cli.device) {
+        (Some(_), ..) => None,
+        (None, None, None) => Some(pick_input(&gilrs).ok_or(())?),
+        (None, kind, query) => {
+            let device = find_device(&gilrs, kind, query.as_deref());
+            if device.is_none() {
+                error!("Couldn't find that device, try --list-devices");
+                return Err(());
+            }
+            device
+        }
+    };
+
+    let mut editor = None;
+    let config = config::Gamepad::from_file(&watch_file);
+    if let Err(e) = config.map(|(c, deps)| {
+        if let Err(e) = watcher.set_dependencies(deps) {
+            error!("Couldn't watch the files that the config uses: {e}");
+        }
+        editor = Some(Editor::new(c.clone()));
+        let res = match (replay, device) {
+            (Some(replay), _) => gamepad.load::<Slippi>(&c, replay),
+            (None, Some(Device::Usb(id))) => {
+                gamepad.load::<UsbGamepad>(&c, (Gilrs::new().unwrap(), id))
+            }
+            (None, Some(Device::Serial(path))) => {
+                gamepad.load::<Haybox>(&c, (path, cli.baud))
+            }
+            #[cfg(target_os = "linux")]
+            (None, Some(Device::Dolphin(port))) => {
+                gamepad.load::<dolphin::Dolphin>(&c, port)
+            }
+            #[cfg(target_os = "linux")]
+            (None, Some(Device::Keyboard(path))) => {
+                gamepad.load::<keyboard::Keyboard>(&c, path)
+            }
+            (None, None) => unreachable!("there's always a replay or a device"),
         };
         if let Err(e) = res {
             error!("Failed to initialize backend {e:?}");
         }
     }) {
-        error!("Invalid config: {e}\n")
+        error!("Invalid config: {e:#}\n")
     }
 
     let options = WindowOptions {
@@ -71,10 +229,10 @@ fn main() -> Result<(), ()> {
     let mut width = img.width() as usize;
     let mut height = img.height() as usize;
     let mut buf = vec![0u32; width * height];
-    gamepad.render(&mut img);
-    update_screen(&mut img, &mut buf);
+    draw(&gamepad, editor.as_ref(), &mut img, &mut buf);
     let mut window = Window::new("Test", width, height, options).unwrap();
-    window.set_target_fps(FPS);
+    window.set_target_fps(cli.fps);
+    let mut title = String::from("Test");
     while watcher.rx.try_recv().is_ok() {} // drain initial file changes
 
     let mut times = 0;
@@ -82,6 +240,7 @@ fn main() -> Result<(), ()> {
     while window.is_open()
         && !(window.is_key_down(Key::Escape) || window.is_key_down(Key::Q))
     {
+        let mut redraw = false;
         while let Ok(DebouncedEvent { path, kind: DebouncedEventKind::Any }) =
             watcher.rx.try_recv()
         {
@@ -91,35 +250,76 @@ fn main() -> Result<(), ()> {
             }
             last_change = now;
 
-            if watch_file == path {
-                match toml::from_str(&fs::read_to_string(path).unwrap()) {
-                    Ok(config) => {
+            if watcher.is_relevant(&path) {
+                match config::Gamepad::from_file(&watch_file) {
+                    Ok((config, deps)) => {
                         println!("Reloaded config...");
                         gamepad.reload(&config);
-                        let bounds = gamepad.inputs.bounds();
-                        if width != bounds.right() as usize
-                            || height != bounds.bottom() as usize
-                        {
-                            info!("Resized, making new window...");
-                            img = create_image(&gamepad.inputs);
-                            width = img.width() as usize;
-                            height = img.height() as usize;
-                            buf = vec![0u32; width * height];
-                            window = Window::new("Test", width, height, options).unwrap();
-                            window.set_target_fps(FPS);
+                        match &mut editor {
+                            Some(editor) => editor.reload(config),
+                            None => editor = Some(Editor::new(config)),
                         }
-                        gamepad.render(&mut img);
-                        update_screen(&mut img, &mut buf);
+                        if let Err(e) = watcher.set_dependencies(deps) {
+                            error!("Couldn't watch the files that the config uses: {e}");
+                        }
+                        redraw = true;
+                    }
+                    Err(e) => {
+                        error!("Config reload failed: {e:#}");
+                        check::log(&watch_file);
                     }
-                    Err(e) => error!("Config reload failed: {}", e),
                 }
             }
         }
 
+        if let Some(editor) = &mut editor {
+            if window.is_key_pressed(Key::E, KeyRepeat::No) {
+                editor.enabled = !editor.enabled;
+                redraw = true;
+            }
+            let selected = editor.selected;
+            if editor.enabled && editor.update(&window, &gamepad.inputs, &watch_file) {
+                gamepad.reload(&editor.config);
+                redraw = true;
+            }
+            redraw |= editor.selected != selected;
+        }
+
+        if redraw {
+            let bounds = gamepad.inputs.bounds();
+            if width != bounds.right() as usize || height != bounds.bottom() as usize {
+                info!("Resized, making new window...");
+                img = create_image(&gamepad.inputs);
+                width = img.width() as usize;
+                height = img.height() as usize;
+                buf = vec![0u32; width * height];
+                window = Window::new(&title, width, height, options).unwrap();
+                window.set_target_fps(cli.fps);
+            }
+            draw(&gamepad, editor.as_ref(), &mut img, &mut buf);
+        }
+        let status = format!("Test{}", editor.as_ref().map_or("", Editor::status));
+        if title != status {
+            window.set_title(&status);
+            title = status;
+        }
+
+        let editing = editor.as_ref().is_some_and(|e| e.enabled);
+        if let Some(backend) = gamepad.backend.as_mut().filter(|_| !editing) {
+            if window.is_key_pressed(Key::Space, KeyRepeat::No) {
+                backend.toggle_pause();
+            }
+            if window.is_key_pressed(Key::Left, KeyRepeat::Yes) {
+                backend.seek(-SECOND);
+            }
+            if window.is_key_pressed(Key::Right, KeyRepeat::Yes) {
+                backend.seek(SECOND);
+            }
+        }
+
         let frame_start = Instant::now();
-        if gamepad.poll() || BENCHMARK {
-            gamepad.render(&mut img);
-            update_screen(&mut img, &mut buf);
+        if gamepad.poll() || cli.benchmark {
+            draw(&gamepad, editor.as_ref(), &mut img, &mut buf);
         }
         let frame_end = Instant::now();
         total += (frame_end - frame_start).as_micros();
@@ -130,20 +330,134 @@ fn main() -> Result<(), ()> {
     Ok(())
 }
 
-// returns selected id
-fn pick_input(max_gamepads: usize, gilrs: &Gilrs) -> usize {
-    println!("\nDetected {} gamepads:", max_gamepads);
-    for (id, name) in usb::get_devices(gilrs) {
-        println!("{id}: {name}");
+/// Prints what [`check::check`] finds, and fails if there are any errors
+fn check(options: &CheckOptions) -> Result<(), ()> {
+    let mut backend = None;
+    if options.backend.is_some() || options.device.is_some() {
+        let gilrs = Gilrs::new().unwrap();
+        let Some(device) = find_device(&gilrs, options.backend, options.device.as_deref())
+        else {
+            error!("Couldn't find that device, try --list-devices");
+            return Err(());
+        };
+        let opened = open(device, options.baud)
+            .map_err(|e| error!("Failed to initialize backend {e:?}"))?;
+        backend = Some(opened);
     }
-    for (id, (name, desc)) in haybox::get_ports().iter().enumerate() {
-        println!("{}: {name} {desc}", id + 10);
+    let backend = backend.as_deref_mut().map(|b| b as &mut dyn Backend);
+    let diagnostics = check::check(&options.layout, backend);
+    for diagnostic in &diagnostics {
+        println!("{diagnostic}");
+    }
+    let errors = diagnostics.iter().filter(|d| d.severity == check::Severity::Error);
+    match (errors.count(), diagnostics.len()) {
+        (0, 0) => println!("No problems in {}", options.layout.display()),
+        (0, warnings) => println!("{warnings} warning(s)"),
+        (errors, all) => {
+            println!("{errors} error(s), {} warning(s)", all - errors);
+            return Err(());
+        }
+    }
+    Ok(())
+}
+
+/// Starts reading from a device before there's a layout, so it gets one with
+/// [`Backend::reload`]
+fn open(device: Device, baud: u32) -> Result<Box<dyn Backend>, Report> {
+    let inputs = Inputs::default();
+    Ok(match device {
+        Device::Usb(id) => {
+            Box::new(UsbGamepad::init((Gilrs::new().unwrap(), id), &inputs)?)
+        }
+        Device::Serial(path) => Box::new(Haybox::init((path, baud), &inputs)?),
+        #[cfg(target_os = "linux")]
+        Device::Dolphin(port) => Box::new(dolphin::Dolphin::init(port, &inputs)?),
+        #[cfg(target_os = "linux")]
+        Device::Keyboard(path) => Box::new(keyboard::Keyboard::init(path, &inputs)?),
+    })
+}
+
+/// Every device that's plugged in along with a description of it
+fn list_devices(gilrs: &Gilrs) -> Vec<(Device, String)> {
+    let mut usb: Vec<_> = usb::get_devices(gilrs).into_iter().collect();
+    usb.sort();
+    let mut devices: Vec<_> =
+        usb.into_iter().map(|(id, name)| (Device::Usb(id), name)).collect();
+    let mut ports: Vec<_> = haybox::get_ports().into_iter().collect();
+    ports.sort();
+    devices.extend(ports.into_iter().map(|(name, desc)| (Device::Serial(name), desc)));
+    #[cfg(target_os = "linux")]
+    {
+        devices.extend(
+            (1..=4).map(|port| {
+                (Device::Dolphin(port), format!("Dolphin (melee) port {port}"))
+            }),
+        );
+        devices.push((Device::Keyboard(None), "All keyboards".into()));
+        for (path, name) in keyboard::get_devices() {
+            devices.push((Device::Keyboard(Some(path)), name));
+        }
+    }
+    devices
+}
+
+/// Looks for a device by its --device arg, then by part of its description. Serial
+/// ports and keyboards can be paths that weren't listed.
+fn find_device(
+    gilrs: &Gilrs,
+    kind: Option<BackendKind>,
+    query: Option<&str>,
+) -> Option<Device> {
+    let devices: Vec<_> = list_devices(gilrs)
+        .into_iter()
+        .filter(|(d, _)| kind.is_none_or(|kind| d.kind() == kind))
+        .collect();
+    let Some(query) = query else { return devices.into_iter().next().map(|(d, _)| d) };
+    let lowercase = query.to_lowercase();
+    devices
+        .iter()
+        .find(|(d, _)| d.arg() == query)
+        .or_else(|| {
+            devices.iter().find(|(_, desc)| desc.to_lowercase().contains(&lowercase))
+        })
+        .map(|(d, _)| d.clone())
+        .or_else(|| match kind {
+            Some(BackendKind::Serial) => Some(Device::Serial(query.into())),
+            #[cfg(target_os = "linux")]
+            Some(BackendKind::Keyboard) => Some(Device::Keyboard(Some(query.into()))),
+            _ => None,
+        })
+}
+
+/// Asks which device to use, `None` if stdin closes first
+fn pick_input(gilrs: &Gilrs) -> Option<Device> {
+    let devices = list_devices(gilrs);
+    println!();
+    for (i, (device, description)) in devices.iter().enumerate() {
+        let kind = device.kind().to_possible_value().unwrap();
+        println!("{i}: [{}] {description}", kind.get_name());
+    }
+    loop {
+        print!("\nEnter a number: ");
+        io::stdout().flush().unwrap();
+        let mut line = String::new();
+        if io::stdin().read_line(&mut line).unwrap_or(0) == 0 {
+            return None;
+        }
+        if let Some((device, _)) =
+            line.trim().parse().ok().and_then(|i: usize| devices.get(i))
+        {
+            return Some(device.clone());
+        }
+    }
+}
+
+fn draw(gamepad: &Gamepad, editor: Option<&Editor>, img: &mut Pixmap, buf: &mut [u32]) {
+    gamepad.render(img);
+    if let Some(editor) = editor {
+        editor.draw(img, &gamepad.inputs);
     }
-    print!("\nEnter an id: ");
-    io::stdout().flush().unwrap();
-    let mut line = String::new();
-    io::stdin().read_line(&mut line).unwrap();
-    line.trim().parse().expect("input a number")
+    update_screen(img, buf);
 }
 
 fn update_screen(img: &mut Pixmap, buf: &mut [u32]) {
@@ -0,0 +1,172 @@
+use log::warn;
+
+use crate::{
+    config::Id,
+    gamepad::{DpadSource, InputState, Inputs, MissingId},
+};
+
+/// A gamecube controller as melee sees it. Layouts refer to buttons by their bit in
+/// `buttons`, and to sticks/axes by their index in `analog`:
+///
+/// | bit | button     |   | index | analog       |
+/// |-----|------------|---|-------|--------------|
+/// | 0   | dpad left  |   | 0     | main stick x |
+/// | 1   | dpad right |   | 1     | main stick y |
+/// | 2   | dpad down  |   | 2     | c-stick x    |
+/// | 3   | dpad up    |   | 3     | c-stick y    |
+/// | 4   | Z          |   | 4     | L trigger    |
+/// | 5   | R          |   | 5     | R trigger    |
+/// | 6   | L          |   |       |              |
+/// | 8   | A          |   |       |              |
+/// | 9   | B          |   |       |              |
+/// | 10  | X          |   |       |              |
+/// | 11  | Y          |   |       |              |
+/// | 12  | Start      |   |       |              |
+///
+/// The serial backend uses this too, but with the bits in whatever order its firmware
+/// sends them. Layouts can also use the names in [`BUTTONS`] and [`ANALOG`].
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Controller {
+    pub buttons: u64,
+    /// sticks are -1..1 with down being positive (like every other backend), and
+    /// triggers are 0..1
+    pub analog: [f32; 6],
+}
+
+/// Standard names for the bits in [`Controller::buttons`], the same way SDL maps a
+/// gamecube controller, plus the names on the controller itself
+#[rustfmt::skip]
+pub const BUTTONS: &[(&str, u8)] = &[
+    ("dpad_left", 0), ("dpad_right", 1), ("dpad_down", 2), ("dpad_up", 3),
+    ("right_bumper", 4), ("right_trigger", 5), ("left_trigger", 6),
+    ("south", 8), ("west", 9), ("east", 10), ("north", 11), ("start", 12),
+    ("z", 4), ("r", 5), ("l", 6), ("a", 8), ("b", 9), ("x", 10), ("y", 11),
+];
+
+/// Standard names for the indices in [`Controller::analog`]
+#[rustfmt::skip]
+pub const ANALOG: &[(&str, u8)] = &[
+    ("left_x", 0), ("left_y", 1), ("right_x", 2), ("right_y", 3),
+    ("left_trigger", 4), ("right_trigger", 5),
+    ("c_x", 2), ("c_y", 3), ("l", 4), ("r", 5),
+];
+
+impl Controller {
+    fn analog(&self, id: Option<u8>) -> f32 {
+        id.and_then(|id| self.analog.get(id as usize)).copied().unwrap_or_default()
+    }
+
+    fn pressed(&self, id: Option<u8>) -> bool {
+        id.is_some_and(|id| id < 64 && self.buttons & 1 << id != 0)
+    }
+}
+
+/// The ids that a layout asked for, so that a [`Controller`] can be copied into an
+/// [`InputState`]. Anything that doesn't resolve is `None` and never shows as pressed.
+#[derive(Debug, Default)]
+pub struct Mapping {
+    buttons: Vec<Option<u8>>,
+    sticks: Vec<(Option<u8>, Option<u8>)>,
+    axes: Vec<Option<u8>>,
+    dpads: Vec<Dpad>,
+    /// the ids that didn't resolve
+    pub missing: Vec<MissingId>,
+}
+
+#[derive(Debug)]
+enum Dpad {
+    Hat((Option<u8>, bool), (Option<u8>, bool)),
+    /// up, down, left, right
+    Buttons([Option<u8>; 4]),
+}
+
+/// `names` along with how many there are, since indices can go up to that
+type Ids<'a> = (&'a [(&'a str, u8)], u8);
+
+fn resolve(
+    id: &Id,
+    (names, count): Ids,
+    (key, index): (&'static str, usize),
+    missing: &mut Vec<MissingId>,
+) -> Option<u8> {
+    let resolved = id.resolve(names, |i| (i < count).then_some(i));
+    if resolved.is_none() {
+        warn!("{id} in {key} {index} isn't on this controller");
+        missing.push(MissingId { key, index, id: id.clone() });
+    }
+    resolved
+}
+
+impl Mapping {
+    /// `bits` names the button bits in whatever order the backend has them
+    pub fn new(inputs: &Inputs, bits: &[(&str, u8)]) -> Self {
+        let (bits, analog) = ((bits, u64::BITS as u8), (ANALOG, 6));
+        let mut missing = Vec::new();
+        let mut find = |id, ids, at| resolve(id, ids, at, &mut missing);
+        let buttons = (inputs.buttons.iter().enumerate())
+            .map(|(i, b)| find(&b.id, bits, ("buttons", i)))
+            .collect();
+        let sticks = (inputs.sticks.iter().enumerate())
+            .map(|(i, s)| {
+                (find(&s.x.id, analog, ("sticks", i)), find(&s.y.id, analog, ("sticks", i)))
+            })
+            .collect();
+        let axes = (inputs.axes.iter().enumerate())
+            .map(|(i, a)| find(&a.axis.id, analog, ("axes", i)))
+            .collect();
+        let dpads = (inputs.dpads.iter().enumerate())
+            .map(|(i, d)| match &d.source {
+                DpadSource::Hat(x, y) => Dpad::Hat(
+                    (find(&x.id, analog, ("dpads", i)), x.invert),
+                    (find(&y.id, analog, ("dpads", i)), y.invert),
+                ),
+                DpadSource::Buttons(ids) => {
+                    Dpad::Buttons(ids.each_ref().map(|id| find(id, bits, ("dpads", i))))
+                }
+            })
+            .collect();
+        Self { buttons, sticks, axes, dpads, missing }
+    }
+
+    /// returns whether anything changed
+    pub fn apply(&self, controller: &Controller, state: &mut InputState) -> bool {
+        let mut changed = false;
+        for (&id, old) in self.buttons.iter().zip(&mut state.buttons) {
+            let new = controller.pressed(id);
+            changed |= *old != new;
+            *old = new;
+        }
+        for (&(x, y), old) in self.sticks.iter().zip(&mut state.sticks) {
+            let new = (controller.analog(x), controller.analog(y));
+            changed |= *old != new;
+            *old = new;
+        }
+        for (&id, old) in self.axes.iter().zip(&mut state.axes) {
+            let new = match id {
+                // sticks get treated like any other full range axis
+                Some(0..4) => (controller.analog(id) + 1.0) / 2.0,
+                _ => controller.analog(id),
+            };
+            changed |= *old != new;
+            *old = new;
+        }
+        for (source, old) in self.dpads.iter().zip(&mut state.dpads) {
+            let mut new = *old;
+            match *source {
+                Dpad::Hat((x, invert_x), (y, invert_y)) => {
+                    new.set_x(controller.analog(x), invert_x);
+                    new.set_y(controller.analog(y), invert_y);
+                }
+                Dpad::Buttons([up, down, left, right]) => {
+                    new.up = controller.pressed(up);
+                    new.down = controller.pressed(down);
+                    new.left = controller.pressed(left);
+                    new.right = controller.pressed(right);
+                }
+            }
+            changed |= *old != new;
+            *old = new;
+        }
+        changed
+    }
+}
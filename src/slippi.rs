@@ -0,0 +1,239 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+use color_eyre::{
+    Report,
+    eyre::{Context, eyre},
+};
+use log::{info, warn};
+
+use crate::{
+    gamepad::{Backend, InputState, Inputs, MissingId},
+    melee::{self, Controller, Mapping},
+};
+
+const FRAME: Duration = Duration::from_nanos(1_000_000_000 / 60);
+/// `{"raw":[$U#l` in UBJSON, followed by the length of the raw event stream
+const HEADER: &[u8] = b"{U\x03raw[$U#l";
+const EVENT_PAYLOADS: u8 = 0x35;
+const PRE_FRAME_UPDATE: u8 = 0x37;
+
+/// Plays back the inputs of one player from a slippi replay in real time
+#[derive(Debug)]
+pub struct Slippi {
+    frames: Vec<Controller>,
+    mapping: Mapping,
+    /// where playback was when it was last started/paused/seeked
+    offset: Duration,
+    /// `None` while paused
+    started: Option<Instant>,
+    current: Option<usize>,
+}
+
+impl Slippi {
+    fn frame(&self) -> usize {
+        let elapsed = self.offset + self.started.map(|s| s.elapsed()).unwrap_or_default();
+        let frame = (elapsed.as_nanos() / FRAME.as_nanos()) as usize;
+        frame.min(self.frames.len().saturating_sub(1))
+    }
+}
+
+/// Reads the pre-frame updates for every player out of a replay, indexed by port
+fn parse(raw: &[u8]) -> Result<[BTreeMap<i32, Controller>; 4], Report> {
+    let raw = raw.strip_prefix(HEADER).ok_or(eyre!("not a slippi replay"))?;
+    let (len, raw) = raw.split_at_checked(4).ok_or(eyre!("replay is truncated"))?;
+    // replays that are still being written have a length of 0
+    let raw = match u32::from_be_bytes(len.try_into().unwrap()) as usize {
+        0 => raw,
+        len => raw.get(..len).ok_or(eyre!("replay is truncated"))?,
+    };
+
+    let Some(&[EVENT_PAYLOADS, header_len]) = raw.first_chunk() else {
+        return Err(eyre!("replay doesn't start with the event payload sizes"));
+    };
+    let header_len = header_len as usize;
+    let sizes = raw.get(2..header_len + 1).ok_or(eyre!("replay is truncated"))?;
+    let mut payload_sizes = [None; 256];
+    for entry in sizes.chunks_exact(3) {
+        payload_sizes[entry[0] as usize] = Some(u16::from_be_bytes([entry[1], entry[2]]));
+    }
+
+    let mut players: [BTreeMap<i32, Controller>; 4] = Default::default();
+    let mut i = header_len + 1;
+    while let Some(&command) = raw.get(i) {
+        let Some(size) = payload_sizes[command as usize] else {
+            warn!("unknown slippi event {command:#x}, ignoring the rest of the replay");
+            break;
+        };
+        let Some(event) = raw.get(i..i + size as usize + 1) else { break };
+        i += size as usize + 1;
+        if command != PRE_FRAME_UPDATE {
+            continue;
+        }
+
+        let bytes = |at: usize, n: usize| event.get(at..at + n);
+        let u32_at = |at| bytes(at, 4).map(|b| u32::from_be_bytes(b.try_into().unwrap()));
+        let f32_at = |at| u32_at(at).map(f32::from_bits).unwrap_or_default();
+        let (Some(frame), Some(&[port, follower])) = (u32_at(0x01), bytes(0x05, 2)) else {
+            continue;
+        };
+        // ice climbers' nana gets her own updates, but the inputs are popo's
+        if follower != 0 || port > 3 {
+            continue;
+        }
+        let buttons = bytes(0x31, 2).map(|b| u16::from_be_bytes([b[0], b[1]]));
+        players[port as usize].insert(
+            frame as i32,
+            Controller {
+                buttons: buttons.unwrap_or_default() as u64,
+                // melee has up as positive
+                analog: [
+                    f32_at(0x19),
+                    -f32_at(0x1D),
+                    f32_at(0x21),
+                    -f32_at(0x25),
+                    f32_at(0x33),
+                    f32_at(0x37),
+                ],
+            },
+        );
+    }
+    Ok(players)
+}
+
+impl Backend for Slippi {
+    /// the replay, and which port to play back (defaults to the first player)
+    type InitState = (PathBuf, Option<u8>);
+    type Err = Report;
+
+    fn init((path, port): Self::InitState, inputs: &Inputs) -> Result<Self, Self::Err> {
+        let raw = fs::read(&path).with_context(|| format!("couldn't read {path:?}"))?;
+        let mut players =
+            parse(&raw).with_context(|| format!("couldn't parse {path:?}"))?;
+        let port = match port {
+            Some(p @ 1..=4) => p,
+            Some(p) => return Err(eyre!("port {p} doesn't exist, pick one from 1 to 4")),
+            None => (1..=4)
+                .find(|&p| !players[p as usize - 1].is_empty())
+                .ok_or(eyre!("replay doesn't have any players"))?,
+        };
+        let frames: Vec<_> =
+            std::mem::take(&mut players[port as usize - 1]).into_values().collect();
+        if frames.is_empty() {
+            return Err(eyre!("nobody was playing on port {port}"));
+        }
+        info!("playing back {} frames from port {port}", frames.len());
+
+        Ok(Self {
+            frames,
+            mapping: Mapping::new(inputs, melee::BUTTONS),
+            offset: Duration::ZERO,
+            started: Some(Instant::now()),
+            current: None,
+        })
+    }
+
+    fn poll(&mut self, state: &mut InputState) -> bool {
+        let frame = self.frame();
+        if self.current == Some(frame) {
+            return false;
+        }
+        self.current = Some(frame);
+        self.mapping.apply(&self.frames[frame], state)
+    }
+
+    fn connected(&self) -> bool {
+        true
+    }
+
+    fn missing_ids(&self) -> &[MissingId] {
+        &self.mapping.missing
+    }
+
+    fn reload(&mut self, inputs: &Inputs) {
+        self.mapping = Mapping::new(inputs, melee::BUTTONS);
+        self.current = None;
+    }
+
+    fn toggle_pause(&mut self) {
+        match self.started.take() {
+            Some(started) => self.offset += started.elapsed(),
+            None => self.started = Some(Instant::now()),
+        }
+    }
+
+    fn seek(&mut self, frames: i64) {
+        let now = FRAME * self.frame() as u32;
+        let delta = FRAME * frames.unsigned_abs() as u32;
+        let end = FRAME * self.frames.len() as u32;
+        self.offset =
+            if frames < 0 { now.saturating_sub(delta) } else { (now + delta).min(end) };
+        self.started = self.started.map(|_| Instant::now());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PRE_FRAME_SIZE: u8 = 0x3F;
+
+    /// A pre-frame update with everything else left at 0
+    fn pre_frame(frame: i32, port: u8, follower: u8, c: &Controller) -> Vec<u8> {
+        let mut event = vec![0; PRE_FRAME_SIZE as usize + 1];
+        event[0] = PRE_FRAME_UPDATE;
+        event[0x01..0x05].copy_from_slice(&frame.to_be_bytes());
+        event[0x05] = port;
+        event[0x06] = follower;
+        let [x, y, cx, cy, l, r] = c.analog;
+        for (at, value) in
+            [(0x19, x), (0x1D, -y), (0x21, cx), (0x25, -cy), (0x33, l), (0x37, r)]
+        {
+            event[at..at + 4].copy_from_slice(&value.to_be_bytes());
+        }
+        event[0x31..0x33].copy_from_slice(&(c.buttons as u16).to_be_bytes());
+        event
+    }
+
+    fn replay(events: &[Vec<u8>]) -> Vec<u8> {
+        let mut raw = vec![EVENT_PAYLOADS, 4, PRE_FRAME_UPDATE, 0, PRE_FRAME_SIZE];
+        raw.extend(events.concat());
+        let mut replay = HEADER.to_vec();
+        replay.extend((raw.len() as u32).to_be_bytes());
+        replay.extend(raw);
+        // the metadata that comes after the raw events
+        replay.extend(b"U\x08metadata{}}");
+        replay
+    }
+
+    #[test]
+    fn reads_pre_frame_updates() {
+        let popo =
+            Controller { buttons: 0x0100, analog: [0.5, -0.25, 0.0, 1.0, 0.75, 0.0] };
+        let nana = Controller { buttons: 0x0200, ..Default::default() };
+        let falco = Controller { buttons: 0x1000, analog: [-1.0, 0.0, 0.0, 0.0, 0.0, 0.3] };
+        let players = parse(&replay(&[
+            pre_frame(-123, 0, 0, &popo),
+            pre_frame(-123, 0, 1, &nana),
+            pre_frame(-123, 2, 0, &falco),
+        ]))
+        .unwrap();
+        assert_eq!(players[0].len(), 1);
+        assert_eq!(players[0][&-123], popo);
+        assert!(players[1].is_empty());
+        assert_eq!(players[2][&-123], falco);
+        assert!(players[3].is_empty());
+    }
+
+    #[test]
+    fn truncated_replays() {
+        let replay = replay(&[pre_frame(-123, 0, 0, &Controller::default())]);
+        let error = parse(&replay[..replay.len() - 20]).unwrap_err();
+        assert_eq!(error.to_string(), "replay is truncated");
+        assert_eq!(parse(&replay[..4]).unwrap_err().to_string(), "not a slippi replay");
+    }
+}
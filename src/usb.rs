@@ -1,23 +1,43 @@
 use std::collections::HashMap;
 
 use color_eyre::eyre::{eyre, Report};
-use gilrs_core::{AxisInfo, EvCode, Gilrs};
-use log::{error, warn};
+use gilrs_core::{AxisInfo, EvCode, Gamepad, Gilrs, native_ev_codes as nec};
+use log::{info, warn};
 
-use crate::gamepad::{Backend, InputState, Inputs};
+use crate::{
+    config::Id,
+    controllerdb::{Element, Range},
+    gamepad::{Backend, Direction, DpadSource, InputState, Inputs, MissingId},
+};
 
 #[derive(Debug)]
 pub struct UsbGamepad {
     handle: Gilrs,
-    buttons: HashMap<EvCode, usize>,
-    axes: HashMap<EvCode, AxisIndex>,
+    buttons: HashMap<EvCode, Vec<ButtonIndex>>,
+    axes: HashMap<EvCode, Vec<AxisIndex>>,
     device_id: usize,
+    connected: bool,
+    missing: Vec<MissingId>,
 }
 
 #[derive(Copy, Clone, Debug)]
-enum AxisIndex {
-    Stick(usize, Xy),
+enum ButtonIndex {
     Single(usize),
+    Dpad(usize, Direction),
+    /// an axis in the layout that the device only has a button for
+    Axis(usize),
+}
+
+/// The `bool`s invert the axis
+#[derive(Copy, Clone, Debug)]
+enum AxisIndex {
+    Stick(usize, Xy, bool),
+    Single(usize, Range, bool),
+    Hat(usize, Xy, bool),
+    /// buttons and dpad directions that the device reports as (part of) an axis, like
+    /// analog triggers or hats
+    Button(usize, Range, bool),
+    Dpad(usize, Direction, Range, bool),
 }
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
@@ -26,43 +46,201 @@ enum Xy {
     Y,
 }
 
+/// Where one of the layout's inputs comes from on this device
+#[derive(Copy, Clone, Debug)]
+enum Source {
+    Button(EvCode),
+    Axis(EvCode, Range, bool),
+}
+
+/// Names for gilrs' standard inputs, which it maps the same way on every platform. The
+/// bumpers are `BTN_LT`/`BTN_RT` and the triggers' buttons are `BTN_LT2`/`BTN_RT2`.
+/// These are used for anything the mapping database doesn't cover, like controllers
+/// that aren't in it or platforms that it has no entry for.
+const BUTTONS: &[(&str, EvCode)] = &[
+    ("south", nec::BTN_SOUTH),
+    ("east", nec::BTN_EAST),
+    ("north", nec::BTN_NORTH),
+    ("west", nec::BTN_WEST),
+    ("c", nec::BTN_C),
+    ("z", nec::BTN_Z),
+    ("left_bumper", nec::BTN_LT),
+    ("right_bumper", nec::BTN_RT),
+    ("left_trigger", nec::BTN_LT2),
+    ("right_trigger", nec::BTN_RT2),
+    ("select", nec::BTN_SELECT),
+    ("start", nec::BTN_START),
+    ("mode", nec::BTN_MODE),
+    ("left_thumb", nec::BTN_LTHUMB),
+    ("right_thumb", nec::BTN_RTHUMB),
+    ("dpad_up", nec::BTN_DPAD_UP),
+    ("dpad_down", nec::BTN_DPAD_DOWN),
+    ("dpad_left", nec::BTN_DPAD_LEFT),
+    ("dpad_right", nec::BTN_DPAD_RIGHT),
+];
+
+const AXES: &[(&str, EvCode)] = &[
+    ("left_x", nec::AXIS_LSTICKX),
+    ("left_y", nec::AXIS_LSTICKY),
+    ("right_x", nec::AXIS_RSTICKX),
+    ("right_y", nec::AXIS_RSTICKY),
+    ("left_trigger", nec::AXIS_LEFTZ),
+    ("right_trigger", nec::AXIS_RIGHTZ),
+    ("dpad_x", nec::AXIS_DPADX),
+    ("dpad_y", nec::AXIS_DPADY),
+];
+
+/// `g.buttons()` and `g.axes()` in the order SDL numbers them, which is what `bN` and
+/// `aN` in a mapping refer to. On linux SDL puts the joystick and gamepad buttons
+/// before everything else, and leaves hats out of the axes.
+#[cfg(target_os = "linux")]
+fn sdl_order(g: &Gamepad) -> (Vec<EvCode>, Vec<EvCode>) {
+    const BTN_JOYSTICK: u32 = 0x120;
+    const ABS_HATS: std::ops::Range<u32> = 0x10..0x18;
+    let code = |c: &EvCode| c.into_u32() & 0xffff;
+    let (mut buttons, mut rest): (Vec<_>, Vec<_>) =
+        g.buttons().iter().partition(|c| code(c) >= BTN_JOYSTICK);
+    buttons.sort_by_key(code);
+    rest.sort_by_key(code);
+    buttons.append(&mut rest);
+    let axes = g.axes().iter().filter(|c| !ABS_HATS.contains(&code(c))).copied().collect();
+    (buttons, axes)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn sdl_order(g: &Gamepad) -> (Vec<EvCode>, Vec<EvCode>) {
+    (g.buttons().to_vec(), g.axes().to_vec())
+}
+
 impl UsbGamepad {
     fn load_mappings(&mut self, inputs: &Inputs) -> Option<()> {
         let g = self.handle.gamepad(self.device_id)?;
-        self.buttons = inputs
-            .buttons
-            .iter()
-            .enumerate()
-            .filter_map(|(i, b)| {
-                if let Some(&evcode) = g.buttons().get(b.id as usize) {
-                    Some((evcode, i))
-                } else {
-                    warn!("Couldn't find button {i}");
-                    None
-                }
-            })
-            .collect();
+        let mapping = inputs.mappings.get(g.uuid());
+        if let Some(mapping) = mapping {
+            info!("Using the controller mapping for {}", mapping.name);
+        }
+        let (sdl_buttons, sdl_axes) = sdl_order(g);
+        let element = |e: Element| match e {
+            Element::Button(i) => Some(Source::Button(*sdl_buttons.get(i)?)),
+            Element::Axis { index, range, invert } => {
+                Some(Source::Axis(*sdl_axes.get(index)?, range, invert))
+            }
+            // other hats don't have a platform independent code
+            Element::Hat { index: 0, mask } => match mask {
+                1 => Some(Source::Axis(nec::AXIS_DPADY, Range::Negative, false)),
+                2 => Some(Source::Axis(nec::AXIS_DPADX, Range::Positive, false)),
+                4 => Some(Source::Axis(nec::AXIS_DPADY, Range::Positive, false)),
+                8 => Some(Source::Axis(nec::AXIS_DPADX, Range::Negative, false)),
+                _ => None,
+            },
+            Element::Hat { .. } => None,
+        };
+        // numbers are indices into gilrs' lists, names go through the mapping if there
+        // is one for this platform and otherwise fall back to gilrs' standard codes, as
+        // long as the device has them
+        let named = |name: &str| mapping.and_then(|m| m.get(name)).and_then(element);
+        let button = |id: &Id| match id {
+            Id::Index(i) => g.buttons().get(*i as usize).copied().map(Source::Button),
+            Id::Name(name) => named(name).or_else(|| {
+                (id.resolve(BUTTONS, |_| None))
+                    .filter(|code| g.buttons().contains(code))
+                    .map(Source::Button)
+            }),
+        };
+        let axis = |id: &Id| {
+            let full = |code| Source::Axis(code, Range::Full, false);
+            match id {
+                Id::Index(i) => g.axes().get(*i as usize).copied().map(full),
+                Id::Name(name) => named(name).or_else(|| {
+                    (id.resolve(AXES, |_| None))
+                        .filter(|code| g.axes().contains(code))
+                        .map(full)
+                }),
+            }
+        };
+        let mut missing = Vec::new();
+        let mut found = |source: Option<Source>, id: &Id, key, index| {
+            if source.is_none() {
+                warn!("Couldn't find {id} for {key} {index}");
+                missing.push(MissingId { key, index, id: id.clone() });
+            }
+            source
+        };
+        // mappings only have a hat's directions, not its axes
+        let hat_directions = |x: &Id| {
+            mapping.is_some() && matches!(x, Id::Name(name) if named(name).is_none())
+        };
 
+        self.buttons.clear();
         self.axes.clear();
+        let mut add_button =
+            |code, index| self.buttons.entry(code).or_default().push(index);
+        let mut add_axis = |code, index| self.axes.entry(code).or_default().push(index);
+        for (i, b) in inputs.buttons.iter().enumerate() {
+            match found(button(&b.id), &b.id, "buttons", i) {
+                Some(Source::Button(code)) => add_button(code, ButtonIndex::Single(i)),
+                Some(Source::Axis(code, range, invert)) => {
+                    add_axis(code, AxisIndex::Button(i, range, invert))
+                }
+                None => {}
+            }
+        }
         for (i, a) in inputs.axes.iter().enumerate() {
-            if let Some(&evcode) = g.axes().get(a.axis.id as usize) {
-                self.axes.insert(evcode, AxisIndex::Single(i));
-            } else {
-                warn!("Couldn't find axis {i}")
+            match found(axis(&a.axis.id), &a.axis.id, "axes", i) {
+                Some(Source::Axis(code, range, invert)) => {
+                    add_axis(code, AxisIndex::Single(i, range, invert))
+                }
+                Some(Source::Button(code)) => add_button(code, ButtonIndex::Axis(i)),
+                None => {}
             }
         }
         for (i, s) in inputs.sticks.iter().enumerate() {
-            if let Some(&evcode) = g.axes().get(s.x.id as usize) {
-                self.axes.insert(evcode, AxisIndex::Stick(i, Xy::X));
-            } else {
-                warn!("Couldn't find stick axis {i}")
+            for (raw, xy) in [(&s.x, Xy::X), (&s.y, Xy::Y)] {
+                match found(axis(&raw.id), &raw.id, "sticks", i) {
+                    Some(Source::Axis(code, _, invert)) => {
+                        add_axis(code, AxisIndex::Stick(i, xy, invert))
+                    }
+                    Some(Source::Button(_)) => warn!("Stick axis {} is a button", raw.id),
+                    None => {}
+                }
             }
-            if let Some(&evcode) = g.axes().get(s.y.id as usize) {
-                self.axes.insert(evcode, AxisIndex::Stick(i, Xy::Y));
-            } else {
-                warn!("Couldn't find stick axis {i}")
+        }
+        for (i, d) in inputs.dpads.iter().enumerate() {
+            let ids = match &d.source {
+                DpadSource::Hat(x, _) if hat_directions(&x.id) => {
+                    ["dpad_up", "dpad_down", "dpad_left", "dpad_right"]
+                        .map(|name| Id::Name(name.to_owned()))
+                }
+                DpadSource::Hat(x, y) => {
+                    for (raw, xy) in [(x, Xy::X), (y, Xy::Y)] {
+                        match found(axis(&raw.id), &raw.id, "dpads", i) {
+                            Some(Source::Axis(code, _, invert)) => {
+                                add_axis(code, AxisIndex::Hat(i, xy, raw.invert != invert))
+                            }
+                            Some(Source::Button(_)) => {
+                                warn!("Dpad axis {} is a button", raw.id)
+                            }
+                            None => {}
+                        }
+                    }
+                    continue;
+                }
+                DpadSource::Buttons(ids) => ids.clone(),
+            };
+            use Direction::*;
+            for (id, dir) in ids.iter().zip([Up, Down, Left, Right]) {
+                match found(button(id), id, "dpads", i) {
+                    Some(Source::Button(code)) => {
+                        add_button(code, ButtonIndex::Dpad(i, dir))
+                    }
+                    Some(Source::Axis(code, range, invert)) => {
+                        add_axis(code, AxisIndex::Dpad(i, dir, range, invert))
+                    }
+                    None => {}
+                }
             }
         }
+        self.missing = missing;
         Some(())
     }
 }
@@ -75,8 +253,15 @@ impl Backend for UsbGamepad {
         (handle, device_id): Self::InitState,
         inputs: &Inputs,
     ) -> Result<Self, Self::Err> {
-        let mut usb =
-            Self { handle, buttons: HashMap::new(), axes: HashMap::new(), device_id };
+        let connected = handle.gamepad(device_id).is_some_and(|g| g.is_connected());
+        let mut usb = Self {
+            handle,
+            buttons: HashMap::new(),
+            axes: HashMap::new(),
+            device_id,
+            connected,
+            missing: Vec::new(),
+        };
         usb.load_mappings(inputs)
             .map(|_| usb)
             .ok_or(eyre!("couldn't open gamepad with index {device_id}"))
@@ -86,6 +271,10 @@ impl Backend for UsbGamepad {
         self.load_mappings(inputs);
     }
 
+    fn missing_ids(&self) -> &[MissingId] {
+        &self.missing
+    }
+
     fn poll(&mut self, state: &mut InputState) -> bool {
         let mut modified = false;
         while let Some(ev) = self.handle.next_event() {
@@ -94,53 +283,79 @@ impl Backend for UsbGamepad {
                 continue;
             }
             use gilrs_core::EventType::*;
-            modified |= match ev.event {
+            match ev.event {
                 ev @ (ButtonPressed(code) | ButtonReleased(code)) => {
                     let new = matches!(ev, ButtonPressed(_));
-                    if let Some(&i) = self.buttons.get(&code) {
-                        state.buttons[i] = new;
-                        true
-                    } else {
-                        false
+                    for &idx in self.buttons.get(&code).into_iter().flatten() {
+                        match idx {
+                            ButtonIndex::Single(i) => state.buttons[i] = new,
+                            ButtonIndex::Dpad(i, dir) => state.dpads[i].set(dir, new),
+                            ButtonIndex::Axis(i) => {
+                                state.axes[i] = if new { 1.0 } else { 0.0 }
+                            }
+                        }
+                        modified = true;
                     }
                 }
                 AxisValueChanged(new, code) => {
-                    if let Some(&idx) = self.axes.get(&code) {
-                        let info = *gamepad.axis_info(code).unwrap();
+                    let Some(indices) = self.axes.get(&code) else { continue };
+                    let new = normalized(new, *gamepad.axis_info(code).unwrap());
+                    for &idx in indices {
                         match idx {
-                            AxisIndex::Stick(i, xy) => {
+                            AxisIndex::Stick(i, xy, invert) => {
                                 let s = &mut state.sticks[i];
+                                let new = if invert { -new } else { new };
                                 if xy == Xy::X {
-                                    s.0 = normalized(new, info);
+                                    s.0 = new;
                                 } else {
-                                    s.1 = normalized(new, info);
+                                    s.1 = new;
                                 };
-                                true
                             }
-                            AxisIndex::Single(i) => {
-                                let percent = (normalized(new, info) + 1.0) / 2.0;
-                                if state.axes[i] == percent {
-                                    error!("they were the same???"); // TODO: remove
+                            AxisIndex::Single(i, range, invert) => {
+                                state.axes[i] = amount(new, range, invert);
+                            }
+                            AxisIndex::Hat(i, xy, invert) => {
+                                let d = &mut state.dpads[i];
+                                if xy == Xy::X {
+                                    d.set_x(new, invert);
+                                } else {
+                                    d.set_y(new, invert);
                                 }
-                                state.axes[i] = percent;
-                                true
+                            }
+                            AxisIndex::Button(i, range, invert) => {
+                                state.buttons[i] = amount(new, range, invert) > 0.5;
+                            }
+                            AxisIndex::Dpad(i, dir, range, invert) => {
+                                state.dpads[i].set(dir, amount(new, range, invert) > 0.5);
                             }
                         }
-                    } else {
-                        false
+                        modified = true;
                     }
                 }
-                _ev @ (Connected | Disconnected) => {
-                    // self.connected = ev == Connected;
-                    true
+                ev @ (Connected | Disconnected) => {
+                    self.connected = ev == Connected;
+                    modified = true;
                 }
-                _ => todo!(),
+                _ => {}
             }
         }
         modified
     }
+
+    fn connected(&self) -> bool {
+        self.connected
+    }
 }
 
+/// How far into `range` an axis is, from 0 to 1
+fn amount(value: f32, range: Range, invert: bool) -> f32 {
+    let value = if invert { -value } else { value };
+    match range {
+        Range::Full => (value + 1.0) / 2.0,
+        Range::Positive => value.max(0.0),
+        Range::Negative => (-value).max(0.0),
+    }
+}
 fn remap(from: (f32, f32), to: (f32, f32), s: f32) -> f32 {
     to.0 + (s - from.0) * (to.1 - to.0) / (from.1 - from.0)
 }
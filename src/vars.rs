@@ -0,0 +1,341 @@
+//! `[palette]` colors and `[vars]` numbers that the rest of a layout can use as
+//! `"$name"`. Colors can get a different alpha with `"$name/AA"`, and numbers can be
+//! combined with `+ - * /` and parentheses like `"$left + $gap * 2"`. A literal `$` is
+//! written `$$`.
+
+use std::{collections::HashMap, fmt};
+
+use color_eyre::{
+    Report,
+    eyre::{Context, bail, eyre},
+};
+
+use crate::config::Color;
+
+#[derive(Copy, Clone, Debug)]
+enum Resolved {
+    Color(Color),
+    Number(f64),
+}
+
+impl From<Resolved> for toml::Value {
+    fn from(resolved: Resolved) -> Self {
+        match resolved {
+            Resolved::Color(Color { r, g, b, a }) => {
+                toml::Value::String(format!("#{r:02x}{g:02x}{b:02x}{a:02x}"))
+            }
+            // so that it still works for fields that want an integer
+            Resolved::Number(n) if n.fract() == 0.0 && n.abs() < i64::MAX as f64 => {
+                toml::Value::Integer(n as i64)
+            }
+            Resolved::Number(n) => toml::Value::Float(n),
+        }
+    }
+}
+
+/// Where a value that couldn't be filled in is, like `buttons`, `3`, `pos` for
+/// `buttons[3].pos`. It's the context of errors from [`substitute`].
+#[derive(Clone, Debug)]
+pub struct Location(pub Vec<Step>);
+
+#[derive(Clone, Debug)]
+pub enum Step {
+    Key(String),
+    Index(usize),
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Couldn't fill in ")?;
+        for (i, step) in self.0.iter().enumerate() {
+            match step {
+                Step::Key(key) if i == 0 => write!(f, "{key}")?,
+                Step::Key(key) => write!(f, ".{key}")?,
+                Step::Index(index) => write!(f, "[{index}]")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+struct Scope {
+    palette: toml::Table,
+    vars: toml::Table,
+    resolved: HashMap<String, Resolved>,
+    /// names that are part way through being resolved, to catch ones that use themselves
+    resolving: Vec<String>,
+}
+
+/// Removes `[palette]` and `[vars]` from the layout and replaces every string that uses
+/// them
+pub fn substitute(table: &mut toml::Table) -> Result<(), Report> {
+    let mut take = |key| match table.remove(key) {
+        None => Ok(toml::Table::new()),
+        Some(toml::Value::Table(t)) => Ok(t),
+        Some(other) => Err(eyre!("`{key}` should be a table, not {other}")),
+    };
+    let mut scope = Scope {
+        palette: take("palette")?,
+        vars: take("vars")?,
+        resolved: HashMap::new(),
+        resolving: Vec::new(),
+    };
+    let mut path = Vec::new();
+    for (key, value) in table.iter_mut() {
+        path.push(Step::Key(key.clone()));
+        scope.walk(value, &mut path)?;
+        path.pop();
+    }
+    Ok(())
+}
+
+fn is_name(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+impl Scope {
+    fn walk(
+        &mut self,
+        value: &mut toml::Value,
+        path: &mut Vec<Step>,
+    ) -> Result<(), Report> {
+        match value {
+            toml::Value::String(s) if s.contains('$') => {
+                *value = self.string(s).with_context(|| Location(path.clone()))?;
+            }
+            toml::Value::Table(table) => {
+                for (key, value) in table.iter_mut() {
+                    path.push(Step::Key(key.clone()));
+                    self.walk(value, path)?;
+                    path.pop();
+                }
+            }
+            toml::Value::Array(values) => {
+                for (i, value) in values.iter_mut().enumerate() {
+                    path.push(Step::Index(i));
+                    self.walk(value, path)?;
+                    path.pop();
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn string(&mut self, s: &str) -> Result<toml::Value, Report> {
+        if !s.replace("$$", "").contains('$') {
+            return Ok(toml::Value::String(s.replace("$$", "$")));
+        }
+        Ok(self.eval(s)?.into())
+    }
+
+    /// A single `$name`, a color with a new alpha, or arithmetic
+    fn eval(&mut self, s: &str) -> Result<Resolved, Report> {
+        let s = s.trim();
+        if let Some(name) = s.strip_prefix('$').filter(|n| n.chars().all(is_name)) {
+            return self.get(name);
+        }
+        if let Some((name, alpha)) = s.strip_prefix('$').and_then(|s| s.split_once('/'))
+            && name.chars().all(is_name)
+            && alpha.len() == 2
+            && let Ok(alpha) = u8::from_str_radix(alpha, 16)
+            && let Resolved::Color(color) = self.get(name)?
+        {
+            return Ok(Resolved::Color(Color { a: alpha, ..color }));
+        }
+        let mut expr = Expr { rest: s, scope: self };
+        let n = expr.sum()?;
+        if !expr.rest.trim().is_empty() {
+            bail!("didn't expect {:?} in {s:?}", expr.rest.trim());
+        }
+        Ok(Resolved::Number(n))
+    }
+
+    fn get(&mut self, name: &str) -> Result<Resolved, Report> {
+        if let Some(&resolved) = self.resolved.get(name) {
+            return Ok(resolved);
+        }
+        if self.resolving.iter().any(|n| n == name) {
+            bail!("${name} ends up using itself");
+        }
+        self.resolving.push(name.to_owned());
+        let resolved = match (self.palette.get(name).cloned(), self.vars.get(name).cloned())
+        {
+            (Some(toml::Value::String(s)), _) if s.contains('$') => match self.eval(&s)? {
+                color @ Resolved::Color(_) => color,
+                Resolved::Number(_) => bail!("palette color ${name} is a number"),
+            },
+            (Some(toml::Value::String(s)), _) => Resolved::Color(
+                Color::parse(&s)
+                    .ok_or_else(|| eyre!("palette color ${name} isn't a color"))?,
+            ),
+            (Some(_), _) => bail!("palette color ${name} should be a string like \"#fff\""),
+            (None, Some(toml::Value::Integer(n))) => Resolved::Number(n as f64),
+            (None, Some(toml::Value::Float(n))) => Resolved::Number(n),
+            (None, Some(toml::Value::String(s))) => match self.eval(&s)? {
+                number @ Resolved::Number(_) => number,
+                Resolved::Color(_) => bail!("${name} in [vars] is a color"),
+            },
+            (None, Some(_)) => bail!("${name} in [vars] should be a number"),
+            (None, None) => bail!("there's no ${name} in [palette] or [vars]"),
+        };
+        self.resolving.pop();
+        self.resolved.insert(name.to_owned(), resolved);
+        Ok(resolved)
+    }
+}
+
+/// A recursive descent parser that evaluates as it goes
+struct Expr<'a> {
+    rest: &'a str,
+    scope: &'a mut Scope,
+}
+
+impl Expr<'_> {
+    /// Skips whitespace and takes `c` if it's next
+    fn eat(&mut self, c: char) -> bool {
+        self.rest = self.rest.trim_start();
+        self.rest.strip_prefix(c).map(|rest| self.rest = rest).is_some()
+    }
+
+    fn sum(&mut self) -> Result<f64, Report> {
+        let mut n = self.product()?;
+        loop {
+            if self.eat('+') {
+                n += self.product()?;
+            } else if self.eat('-') {
+                n -= self.product()?;
+            } else {
+                return Ok(n);
+            }
+        }
+    }
+
+    fn product(&mut self) -> Result<f64, Report> {
+        let mut n = self.term()?;
+        loop {
+            if self.eat('*') {
+                n *= self.term()?;
+            } else if self.eat('/') {
+                n /= self.term()?;
+            } else {
+                return Ok(n);
+            }
+        }
+    }
+
+    fn term(&mut self) -> Result<f64, Report> {
+        if self.eat('-') {
+            return Ok(-self.term()?);
+        }
+        if self.eat('(') {
+            let n = self.sum()?;
+            if !self.eat(')') {
+                bail!("missing a closing parenthesis");
+            }
+            return Ok(n);
+        }
+        if self.eat('$') {
+            let end = self.rest.find(|c| !is_name(c)).unwrap_or(self.rest.len());
+            let (name, rest) = self.rest.split_at(end);
+            self.rest = rest;
+            return match self.scope.get(name)? {
+                Resolved::Number(n) => Ok(n),
+                Resolved::Color(_) => bail!("${name} is a color, not a number"),
+            };
+        }
+        let end = (self.rest.find(|c: char| !c.is_ascii_digit() && c != '.'))
+            .unwrap_or(self.rest.len());
+        let (number, rest) = self.rest.split_at(end);
+        let n = number.parse().map_err(|_| match self.rest {
+            "" => eyre!("expected a number or $name at the end"),
+            rest => eyre!("expected a number or $name, not {rest:?}"),
+        })?;
+        self.rest = rest;
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn substituted(layout: &str) -> Result<toml::Table, Report> {
+        let mut table: toml::Table = toml::from_str(layout).unwrap();
+        substitute(&mut table).map(|()| table)
+    }
+
+    #[test]
+    fn arithmetic() {
+        let table = substituted(
+            r#"
+            [vars]
+            a = 2
+            b = "$a + 1"
+            [values]
+            precedence = "$a + $b * 2 - 1"
+            parentheses = "($a + $b) * 2"
+            unary = "-$a - -3"
+            nested = "-(1 + $a) * 2"
+            division = "$b / $a"
+            "#,
+        )
+        .unwrap();
+        let values = &table["values"];
+        assert_eq!(values["precedence"].as_integer(), Some(7));
+        assert_eq!(values["parentheses"].as_integer(), Some(10));
+        assert_eq!(values["unary"].as_integer(), Some(1));
+        assert_eq!(values["nested"].as_integer(), Some(-6));
+        assert_eq!(values["division"].as_float(), Some(1.5));
+        assert!(!table.contains_key("vars"));
+    }
+
+    #[test]
+    fn colors_and_escapes() {
+        let table = substituted(
+            r##"
+            [palette]
+            accent = "#5BCEFA"
+            faded = "$accent/80"
+            [values]
+            accent = "$accent"
+            faded = "$faded"
+            clear = "$faded/00"
+            price = "$$5 or $$$$"
+            "##,
+        )
+        .unwrap();
+        let values = &table["values"];
+        assert_eq!(values["accent"].as_str(), Some("#5bcefaff"));
+        assert_eq!(values["faded"].as_str(), Some("#5bcefa80"));
+        assert_eq!(values["clear"].as_str(), Some("#5bcefa00"));
+        assert_eq!(values["price"].as_str(), Some("$5 or $$"));
+    }
+
+    #[test]
+    fn self_references() {
+        let error = substituted(
+            r#"
+            [vars]
+            a = "$b + 1"
+            b = "$a * 2"
+            [[buttons]]
+            pos = ["$a", 0]
+            "#,
+        )
+        .unwrap_err();
+        assert_eq!(
+            format!("{error:#}"),
+            "Couldn't fill in buttons[0].pos[0]: $a ends up using itself"
+        );
+    }
+
+    #[test]
+    fn whole_numbers_are_integers() {
+        let value = |n| toml::Value::from(Resolved::Number(n));
+        assert_eq!(value(3.0), toml::Value::Integer(3));
+        assert_eq!(value(-4.0), toml::Value::Integer(-4));
+        assert_eq!(value(2.5), toml::Value::Float(2.5));
+        assert_eq!(value(1e300), toml::Value::Float(1e300));
+    }
+}